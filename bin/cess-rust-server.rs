@@ -1,8 +1,13 @@
 use actix_cors::Cors;
-use actix_web::{middleware, App, HttpServer};
+use actix_web::{middleware, web, App, HttpServer};
+use chrono::Utc;
 use dotenv::dotenv;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use cess_rust_server::databases::{establish_connection, sync_revoked_sessions};
 use cess_rust_server::routes::configure;
+use cess_rust_server::vault::{vault_from_env, KeyVault};
 use cess_rust_sdk::chain::{ChainSdk, file::File};
 use cess_rust_sdk::chain::storage_handler::StorageHandler;
 use cess_rust_sdk::config;
@@ -106,9 +111,33 @@ async fn main() -> std::io::Result<()> {
     let explorer_server_host: String = env::var("EXPLORER_SERVER_HOST")
         .unwrap_or_else(|_| "0.0.0.0".to_string());
 
+    // Revoked tokens are checked against an in-memory cache: load the ones
+    // still unexpired, then keep picking up revocations made by other instances.
+    let revocation_sync_interval: u64 = env::var("REVOCATION_SYNC_INTERVAL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+    sync_revoked_sessions(&mut establish_connection(), Utc::now().naive_utc());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(revocation_sync_interval.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let _ = tokio::task::spawn_blocking(|| {
+                sync_revoked_sessions(&mut establish_connection(), Utc::now().naive_utc())
+            })
+            .await;
+        }
+    });
+
+    // One key vault is shared by every worker.
+    let vault: Arc<dyn KeyVault> = Arc::from(vault_from_env().expect("Error opening the key vault"));
+    let vault = web::Data::from(vault);
+
     println!("Welcome Face Wallet!");
-    let _ = HttpServer::new(|| {
+    let _ = HttpServer::new(move || {
         App::new()
+            .app_data(vault.clone())
             .wrap(Cors::permissive())
             .wrap(middleware::Logger::default())
             .configure(configure)
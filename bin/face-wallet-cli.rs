@@ -0,0 +1,47 @@
+use cess_rust_server::databases::{
+    derive_public_keys, encrypt_features, encrypt_plaintext_mnemonics, establish_connection, rotate_mnemonic_master_key,
+};
+use cess_rust_server::vault::{derive_evm_addresses, vault_from_env};
+use diesel::pg::PgConnection;
+use dotenvy::dotenv;
+use std::env;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: face-wallet-cli <command>
+
+Commands:
+    encrypt-mnemonics      Encrypt every mnemonic still stored in plaintext
+    rotate-mnemonic-key    Re-wrap mnemonic data keys under MNEMONIC_MASTER_KEY_VERSION
+    encrypt-features       Encrypt face templates and seeds under FEATURE_MASTER_KEY_VERSION
+    derive-evm-addresses   Link an EVM address to every account that has none
+    derive-public-keys     Record the account id behind every address that has none";
+
+fn main() -> ExitCode {
+    // load environment variables
+    dotenv().ok();
+
+    let command = env::args().nth(1).unwrap_or_default();
+    let run: fn(&mut PgConnection) -> anyhow::Result<usize> = match command.as_str() {
+        "encrypt-mnemonics" => encrypt_plaintext_mnemonics,
+        "rotate-mnemonic-key" => rotate_mnemonic_master_key,
+        "encrypt-features" => encrypt_features,
+        "derive-evm-addresses" => |conn| derive_evm_addresses(conn, vault_from_env()?.as_ref()),
+        "derive-public-keys" => derive_public_keys,
+        _ => {
+            println!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let connection = &mut establish_connection();
+    match run(connection) {
+        Ok(count) => {
+            println!("{}: {} row(s) updated", command, count);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("{} failed: {:?}", command, e);
+            ExitCode::FAILURE
+        }
+    }
+}
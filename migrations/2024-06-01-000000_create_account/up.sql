@@ -0,0 +1,8 @@
+CREATE TABLE "account" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "uid" INT8 NOT NULL,
+    "mnemonic" VARCHAR(256),
+    "address" VARCHAR(256),
+    "token" VARCHAR(256),
+    "feature" BYTEA
+);
@@ -0,0 +1 @@
+ALTER TABLE "account" ADD COLUMN "last_match_score" FLOAT4;
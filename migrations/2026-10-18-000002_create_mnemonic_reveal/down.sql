@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS "mnemonic_reveal_audit";
+DROP TABLE IF EXISTS "reveal_ticket";
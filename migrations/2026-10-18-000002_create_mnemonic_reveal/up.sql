@@ -0,0 +1,18 @@
+CREATE TABLE "reveal_ticket" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    "ticket" VARCHAR(64) NOT NULL UNIQUE,
+    "expires_at" TIMESTAMP NOT NULL,
+    "used_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE "mnemonic_reveal_audit" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    "ticket_id" INT8 REFERENCES "reveal_ticket" ("id") ON DELETE SET NULL,
+    "outcome" VARCHAR(32) NOT NULL,
+    "ip" VARCHAR(64),
+    "user_agent" VARCHAR(256),
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+ALTER TABLE "account" DROP COLUMN "mnemonic_key_version";
+ALTER TABLE "account" DROP COLUMN "mnemonic_data_key";
+ALTER TABLE "account" DROP COLUMN "mnemonic_ciphertext";
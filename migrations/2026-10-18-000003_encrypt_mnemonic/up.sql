@@ -0,0 +1,3 @@
+ALTER TABLE "account" ADD COLUMN "mnemonic_ciphertext" BYTEA;
+ALTER TABLE "account" ADD COLUMN "mnemonic_data_key" BYTEA;
+ALTER TABLE "account" ADD COLUMN "mnemonic_key_version" INT4;
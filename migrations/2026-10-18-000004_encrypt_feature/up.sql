@@ -0,0 +1 @@
+ALTER TABLE "account" ADD COLUMN "feature_key_version" INT4;
@@ -0,0 +1 @@
+ALTER TABLE "account" ADD COLUMN "duplicate_of" INT8 REFERENCES "account" ("id") ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+-- Model that produced the stored template; NULL for templates enrolled before
+-- the versioned template format, which are matched as `FACE_LEGACY_MODEL_ID`.
+ALTER TABLE "account" ADD COLUMN "feature_model" VARCHAR(64);
@@ -0,0 +1,8 @@
+CREATE TABLE "auth_challenge" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    "nonce" VARCHAR(64) NOT NULL UNIQUE,
+    "expires_at" TIMESTAMP NOT NULL,
+    "used_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
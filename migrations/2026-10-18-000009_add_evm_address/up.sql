@@ -0,0 +1,2 @@
+-- Lowercase `0x` hex address of the wallet's secp256k1 key.
+ALTER TABLE "account" ADD COLUMN "evm_address" VARCHAR(42) UNIQUE;
@@ -0,0 +1,10 @@
+CREATE TABLE "sub_account" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    "name" VARCHAR(64) NOT NULL,
+    "derivation_path" VARCHAR(256) NOT NULL,
+    "address" VARCHAR(256) NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE ("account_id", "name"),
+    UNIQUE ("account_id", "derivation_path")
+);
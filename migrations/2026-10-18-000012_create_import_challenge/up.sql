@@ -0,0 +1,7 @@
+CREATE TABLE "import_challenge" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "nonce" VARCHAR(64) NOT NULL UNIQUE,
+    "expires_at" TIMESTAMP NOT NULL,
+    "used_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE "account" DROP COLUMN "ss58_prefix";
+ALTER TABLE "account" DROP COLUMN "key_scheme";
@@ -0,0 +1,3 @@
+-- Existing wallets are sr25519 keys with CESS addresses.
+ALTER TABLE "account" ADD COLUMN "key_scheme" VARCHAR(16) NOT NULL DEFAULT 'sr25519';
+ALTER TABLE "account" ADD COLUMN "ss58_prefix" INT4 NOT NULL DEFAULT 11330;
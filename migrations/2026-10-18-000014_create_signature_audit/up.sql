@@ -0,0 +1,13 @@
+CREATE TABLE "signature_audit" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    "sub_account_id" INT8 REFERENCES "sub_account" ("id") ON DELETE SET NULL,
+    "scope" VARCHAR(64) NOT NULL,
+    -- blake2-256 of the signed (wrapped) payload; the payload itself is not kept.
+    "payload_hash" VARCHAR(64) NOT NULL,
+    "payload_len" INT4 NOT NULL,
+    "face_verified" BOOLEAN NOT NULL,
+    "ip" VARCHAR(64),
+    "user_agent" VARCHAR(256),
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
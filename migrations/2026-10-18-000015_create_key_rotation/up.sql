@@ -0,0 +1,14 @@
+CREATE TABLE "key_rotation" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    "old_address" VARCHAR(256) NOT NULL,
+    "old_evm_address" VARCHAR(42),
+    "new_address" VARCHAR(256) NOT NULL,
+    -- The replaced mnemonic, sealed like `account`'s, kept only until its
+    -- balance has been swept to the new address.
+    "old_mnemonic_ciphertext" BYTEA,
+    "old_mnemonic_data_key" BYTEA,
+    "old_mnemonic_key_version" INT4,
+    "swept_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
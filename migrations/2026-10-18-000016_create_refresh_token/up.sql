@@ -0,0 +1,15 @@
+CREATE TABLE "refresh_token" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    -- Shared by every token rotated out of the same login; replaying a used
+    -- token revokes the whole family.
+    "family_id" VARCHAR(64) NOT NULL,
+    -- Hex SHA-256 of the token, which itself is never stored.
+    "token_hash" VARCHAR(64) NOT NULL UNIQUE,
+    "expires_at" TIMESTAMP NOT NULL,
+    "used_at" TIMESTAMP,
+    "revoked_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX "refresh_token_family_id_idx" ON "refresh_token" ("family_id");
@@ -0,0 +1,16 @@
+CREATE TABLE "session" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "account_id" INT8 NOT NULL REFERENCES "account" ("id") ON DELETE CASCADE,
+    -- `jti` claim of the issued access token.
+    "jti" VARCHAR(64) NOT NULL UNIQUE,
+    -- Refresh token family the access token was issued with. A refreshed
+    -- token stays in the family of the login it came from.
+    "family_id" VARCHAR(64) NOT NULL,
+    "device" VARCHAR(256),
+    "ip" VARCHAR(64),
+    "expires_at" TIMESTAMP NOT NULL,
+    "revoked_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX "session_family_id_idx" ON "session" ("family_id");
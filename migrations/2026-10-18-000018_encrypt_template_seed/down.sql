@@ -0,0 +1 @@
+ALTER TABLE "account" DROP COLUMN "template_seed_key_version";
@@ -0,0 +1 @@
+ALTER TABLE "account" ADD COLUMN "template_seed_key_version" INT4;
@@ -0,0 +1,3 @@
+-- Existing wallets hold mnemonics, except keystore imports still stored in plaintext.
+ALTER TABLE "account" ADD COLUMN "secret_kind" VARCHAR(16) NOT NULL DEFAULT 'mnemonic';
+UPDATE "account" SET "secret_kind" = 'seed' WHERE "mnemonic" LIKE '0x%';
@@ -0,0 +1 @@
+DROP INDEX "account_address_key";
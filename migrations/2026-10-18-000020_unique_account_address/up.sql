@@ -0,0 +1,2 @@
+-- Fails while two rows share an address; resolve those duplicates first.
+CREATE UNIQUE INDEX "account_address_key" ON "account" ("address");
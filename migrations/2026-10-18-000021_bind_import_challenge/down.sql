@@ -0,0 +1 @@
+ALTER TABLE "import_challenge" DROP COLUMN "address";
@@ -0,0 +1,3 @@
+-- Outstanding challenges are not bound to any address; they expire within minutes anyway.
+DELETE FROM "import_challenge";
+ALTER TABLE "import_challenge" ADD COLUMN "address" VARCHAR(256) NOT NULL;
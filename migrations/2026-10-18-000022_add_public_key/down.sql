@@ -0,0 +1,2 @@
+DROP INDEX "account_public_key_key";
+ALTER TABLE "account" DROP COLUMN "public_key";
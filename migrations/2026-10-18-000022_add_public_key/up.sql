@@ -0,0 +1,4 @@
+-- The account id an address encodes, the same under every SS58 prefix. Existing
+-- rows are filled in by `face-wallet-cli derive-public-keys`.
+ALTER TABLE "account" ADD COLUMN "public_key" BYTEA;
+CREATE UNIQUE INDEX "account_public_key_key" ON "account" ("public_key");
@@ -0,0 +1 @@
+DROP INDEX "key_rotation_pending_key";
@@ -0,0 +1,4 @@
+-- At most one rotation per account may be waiting for its sweep, so concurrent
+-- rotations cannot both replace the key.
+-- Fails while an account has several; finish or drop the older ones first.
+CREATE UNIQUE INDEX "key_rotation_pending_key" ON "key_rotation" ("account_id") WHERE "swept_at" IS NULL;
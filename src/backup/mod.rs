@@ -0,0 +1,303 @@
+use anyhow::{anyhow, bail, Result};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::ecdh::SharedSecret;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use sha2::Sha256;
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::{decrypt, encrypt};
+use crate::secret::SecretBytes;
+
+pub const MIN_THRESHOLD: u8 = 2;
+
+const SHARE_VERSION: u8 = 1;
+const SHARE_HEADER_LEN: usize = 3;
+const COMPRESSED_KEY_LEN: usize = 33;
+const SHARE_KEY_INFO: &[u8] = b"face-wallet backup share key";
+const SHARE_AAD_PREFIX: &[u8] = b"face-wallet backup share";
+
+// GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1. Addition is XOR.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 is the inverse of a in GF(2^8).
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp != 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// One Shamir share of a secret: the secret's polynomials evaluated at `index`.
+/// `data` is wiped on drop and left out of `Debug`.
+#[derive(Clone, PartialEq)]
+pub struct Share {
+    pub threshold: u8,
+    pub index: u8,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share")
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .field("data", &"[REDACTED]")
+            .finish()
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Share {
+    // Hex of `version | threshold | index | data`.
+    pub fn encode(&self) -> String {
+        let mut bytes = Zeroizing::new(vec![SHARE_VERSION, self.threshold, self.index]);
+        bytes.extend_from_slice(&self.data);
+        hex::encode(bytes.as_slice())
+    }
+
+    pub fn parse(share: &str) -> Result<Self> {
+        let bytes = Zeroizing::new(hex::decode(share.trim())?);
+        if bytes.len() <= SHARE_HEADER_LEN || bytes[0] != SHARE_VERSION {
+            bail!("Unsupported share format");
+        }
+        if bytes[1] < MIN_THRESHOLD || bytes[2] == 0 {
+            bail!("Invalid share header");
+        }
+        Ok(Share {
+            threshold: bytes[1],
+            index: bytes[2],
+            data: bytes[SHARE_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// Splits `secret` into `shares` shares of which any `threshold` recover it.
+pub fn split_secret(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>> {
+    if threshold < MIN_THRESHOLD || threshold > shares {
+        bail!("Threshold must be between {} and the number of shares", MIN_THRESHOLD);
+    }
+    if secret.is_empty() {
+        bail!("Secret must not be empty");
+    }
+
+    let mut coefficients = Zeroizing::new(vec![0u8; secret.len() * (threshold as usize - 1)]);
+    OsRng.try_fill_bytes(&mut coefficients)?;
+
+    let shares = (1..=shares)
+        .map(|x| {
+            let data = secret
+                .iter()
+                .enumerate()
+                .map(|(i, &constant)| {
+                    // Horner's rule, highest coefficient first.
+                    let row = &coefficients[i * (threshold as usize - 1)..(i + 1) * (threshold as usize - 1)];
+                    let y = row.iter().rev().fold(0u8, |acc, &c| gf_mul(acc, x) ^ c);
+                    gf_mul(y, x) ^ constant
+                })
+                .collect();
+            Share { threshold, index: x, data }
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Recovers the secret from at least `threshold` distinct shares of one split.
+pub fn combine_shares(shares: &[Share]) -> Result<SecretBytes> {
+    let first = match shares.first() {
+        Some(share) => share,
+        None => bail!("No shares given"),
+    };
+    let mut used: Vec<&Share> = Vec::new();
+    for share in shares {
+        if share.threshold != first.threshold || share.data.len() != first.data.len() {
+            bail!("Shares do not belong to the same backup");
+        }
+        if !used.iter().any(|s| s.index == share.index) {
+            used.push(share);
+        }
+    }
+    if used.len() < first.threshold as usize {
+        bail!("At least {} distinct shares are required", first.threshold);
+    }
+    used.truncate(first.threshold as usize);
+
+    // Lagrange interpolation at x = 0.
+    let secret = (0..first.data.len())
+        .map(|i| {
+            used.iter().fold(0u8, |acc, share| {
+                let basis = used
+                    .iter()
+                    .filter(|other| other.index != share.index)
+                    .fold(1u8, |basis, other| {
+                        gf_mul(basis, gf_mul(other.index, gf_inv(other.index ^ share.index)))
+                    });
+                acc ^ gf_mul(share.data[i], basis)
+            })
+        })
+        .collect::<Vec<u8>>();
+    Ok(SecretBytes::from(secret))
+}
+
+// AES key of an encrypted share: HKDF-SHA256 of the ECDH secret, bound to both
+// public keys so that it is specific to this ephemeral key and guardian.
+fn share_key(shared: &SharedSecret, ephemeral: &PublicKey, guardian: &PublicKey) -> Result<Zeroizing<[u8; 32]>> {
+    let mut info = SHARE_KEY_INFO.to_vec();
+    info.extend_from_slice(&ephemeral.serialize());
+    info.extend_from_slice(&guardian.serialize());
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, &shared.secret_bytes())
+        .expand(&info, key.as_mut_slice())
+        .map_err(|_| anyhow!("Failed to derive the share key"))?;
+    Ok(key)
+}
+
+// Associated data binding an encrypted share to its index and guardian.
+fn share_aad(index: u8, guardian: &PublicKey) -> Vec<u8> {
+    let mut aad = SHARE_AAD_PREFIX.to_vec();
+    aad.push(index);
+    aad.extend_from_slice(&guardian.serialize());
+    aad
+}
+
+/// Encrypts a share to a guardian's secp256k1 public key (hex, SEC1): ECDH with
+/// an ephemeral key, HKDF-SHA256, then AES-256-GCM with the share index and the
+/// guardian's key as associated data. The result is hex of
+/// `ephemeral public key | index | nonce | ciphertext`.
+pub fn encrypt_share(share: &Share, guardian_key: &str) -> Result<String> {
+    let guardian_key = guardian_key.strip_prefix("0x").unwrap_or(guardian_key);
+    let guardian = PublicKey::from_slice(&hex::decode(guardian_key)?)?;
+
+    let mut seed = Zeroizing::new([0u8; 32]);
+    let ephemeral = loop {
+        OsRng.try_fill_bytes(seed.as_mut_slice())?;
+        if let Ok(key) = SecretKey::from_slice(seed.as_slice()) {
+            break key;
+        }
+    };
+    let ephemeral_public = PublicKey::from_secret_key(&Secp256k1::new(), &ephemeral);
+    let key = share_key(&SharedSecret::new(&guardian, &ephemeral), &ephemeral_public, &guardian)?;
+
+    let encoded = Zeroizing::new(share.encode());
+    let mut sealed = ephemeral_public.serialize().to_vec();
+    sealed.push(share.index);
+    sealed.extend_from_slice(&encrypt(&key, encoded.as_bytes(), &share_aad(share.index, &guardian))?);
+    Ok(hex::encode(sealed))
+}
+
+/// Decrypts a share encrypted by `encrypt_share` with the guardian's secret key.
+pub fn decrypt_share(sealed: &str, guardian_secret: &SecretKey) -> Result<Share> {
+    let sealed = hex::decode(sealed.strip_prefix("0x").unwrap_or(sealed))?;
+    if sealed.len() <= COMPRESSED_KEY_LEN + 1 {
+        bail!("Encrypted share is too short");
+    }
+    let (ephemeral, rest) = sealed.split_at(COMPRESSED_KEY_LEN);
+    let (index, ciphertext) = (rest[0], &rest[1..]);
+
+    let ephemeral = PublicKey::from_slice(ephemeral)?;
+    let guardian = PublicKey::from_secret_key(&Secp256k1::new(), guardian_secret);
+    let key = share_key(&SharedSecret::new(&ephemeral, guardian_secret), &ephemeral, &guardian)?;
+    let encoded = Zeroizing::new(decrypt(&key, ciphertext, &share_aad(index, &guardian))?);
+
+    let share = Share::parse(std::str::from_utf8(&encoded)?)?;
+    if share.index != index {
+        bail!("Encrypted share does not match its index");
+    }
+    Ok(share)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECRET: &[u8] = b"legal winner thank year wave sausage worth useful legal winner thank yellow";
+
+    #[test]
+    fn test_gf_inv() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1, "{}", a);
+        }
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        let shares = split_secret(SECRET, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.data != SECRET));
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[c].clone(), shares[a].clone(), shares[b].clone()];
+                    assert_eq!(combine_shares(&subset).unwrap().expose_secret(), SECRET);
+                }
+            }
+        }
+
+        let parsed: Vec<Share> = shares.iter().map(|share| Share::parse(&share.encode()).unwrap()).collect();
+        assert_eq!(parsed, shares);
+
+        assert!(combine_shares(&shares[..2]).is_err());
+        assert!(combine_shares(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+        let other = split_secret(b"another secret", 3, 5).unwrap();
+        assert!(combine_shares(&[shares[0].clone(), shares[1].clone(), other[2].clone()]).is_err());
+
+        assert!(split_secret(SECRET, 1, 5).is_err());
+        assert!(split_secret(SECRET, 4, 3).is_err());
+    }
+
+    #[test]
+    fn test_guardian_encryption() {
+        let guardian = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let guardian_key = hex::encode(PublicKey::from_secret_key(&Secp256k1::new(), &guardian).serialize());
+        let share = split_secret(SECRET, 2, 2).unwrap()[0].clone();
+
+        let sealed = encrypt_share(&share, &guardian_key).unwrap();
+        assert_eq!(decrypt_share(&sealed, &guardian).unwrap(), share);
+
+        let stranger = SecretKey::from_slice(&[0x24; 32]).unwrap();
+        assert!(decrypt_share(&sealed, &stranger).is_err());
+
+        // The index travels in the clear, but is bound to the ciphertext.
+        let mut tampered = hex::decode(&sealed).unwrap();
+        tampered[COMPRESSED_KEY_LEN] ^= 0x03;
+        assert!(decrypt_share(&hex::encode(tampered), &guardian).is_err());
+    }
+
+    #[test]
+    fn test_share_debug_is_redacted() {
+        let share = Share { threshold: 2, index: 1, data: b"secret share bytes".to_vec() };
+        let debug = format!("{:?}", share);
+        assert!(debug.contains("index: 1"));
+        assert!(!debug.contains("115"));
+        assert!(debug.contains("[REDACTED]"));
+    }
+}
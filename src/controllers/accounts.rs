@@ -1,81 +1,527 @@
-use crate::utils::generate_code;
 use anyhow::{bail, Result};
+use bip39::{Language, Mnemonic};
+use cess_rust_sdk::subxt::ext::sp_core::crypto::{
+    AccountId32, DeriveJunction, Ss58AddressFormat, Ss58Codec,
+};
+use cess_rust_sdk::subxt::ext::sp_core::hashing::blake2_256;
 use cess_rust_sdk::subxt::ext::sp_core::Pair as sp_core_pair;
+use cess_rust_sdk::subxt::ext::sp_core::{ecdsa, ed25519};
+use dotenvy::dotenv;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use sha2::Sha512;
+use std::env;
+use std::fmt;
+use zeroize::Zeroizing;
 
-use sp_keyring::sr25519::sr25519::Pair;
-use subxt_signer::bip39;
-use web3::signing::{keccak256, recover};
+use crate::secret::SecretString;
 
-pub fn generate_mnemonic() -> Result<String> {
-    let random_str = generate_code(16);
-    let entropy = random_str.as_bytes();
-    let mnemonic = bip39::Mnemonic::from_entropy(entropy)?;
-    Ok(mnemonic.to_string())
+use sp_keyring::sr25519::sr25519::{Pair, Public, Signature};
+use web3::signing::{keccak256, recover, Key, SecretKeyRef};
+
+pub const DEFAULT_MNEMONIC_WORD_COUNT: usize = 12;
+pub const MAX_DERIVATION_PATH_LEN: usize = 256;
+// BIP44 path of the first Ethereum account, as used by MetaMask and most EVM wallets.
+pub const EVM_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+// SS58 network prefix of CESS addresses.
+pub const DEFAULT_SS58_PREFIX: u16 = 11330;
+pub const DEFAULT_SIGN_MAX_PAYLOAD_LEN: usize = 4096;
+pub const DEFAULT_SIGN_SENSITIVE_SCOPES: &str = "transfer,staking,governance";
+
+const BYTES_PREFIX: &[u8] = b"<Bytes>";
+const BYTES_SUFFIX: &[u8] = b"</Bytes>";
+
+const BIP32_HARDENED: u32 = 0x8000_0000;
+// SS58 prefixes are 14-bit.
+const MAX_SS58_PREFIX: u16 = 0x3fff;
+
+/// Signature scheme of a wallet's Substrate key.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyScheme {
+    #[default]
+    Sr25519,
+    Ed25519,
+    Ecdsa,
 }
 
-pub fn get_pair(mnemonic: &str, password_override: Option<&str>) -> Result<Pair> {
-    let pair = Pair::from_phrase(mnemonic, password_override);
+impl KeyScheme {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "sr25519" => Ok(KeyScheme::Sr25519),
+            "ed25519" => Ok(KeyScheme::Ed25519),
+            "ecdsa" => Ok(KeyScheme::Ecdsa),
+            _ => bail!("Unsupported key scheme: {}", name),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyScheme::Sr25519 => "sr25519",
+            KeyScheme::Ed25519 => "ed25519",
+            KeyScheme::Ecdsa => "ecdsa",
+        }
+    }
+}
+
+/// Key scheme and SS58 prefix a wallet is created with and recorded on its row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WalletFormat {
+    pub scheme: KeyScheme,
+    pub ss58_prefix: u16,
+}
+
+impl WalletFormat {
+    pub fn new(scheme: KeyScheme, ss58_prefix: u16) -> Result<Self> {
+        let format = Ss58AddressFormat::custom(ss58_prefix);
+        if ss58_prefix > MAX_SS58_PREFIX || format.is_reserved() {
+            bail!("Unsupported SS58 prefix: {}", ss58_prefix);
+        }
+        Ok(WalletFormat { scheme, ss58_prefix })
+    }
+
+    // The deployment's default, from `KEY_SCHEME` and `SS58_PREFIX`.
+    pub fn from_env() -> Result<Self> {
+        dotenv().ok();
+
+        let scheme = match env::var("KEY_SCHEME") {
+            Ok(name) => KeyScheme::parse(&name)?,
+            Err(_) => KeyScheme::default(),
+        };
+        let ss58_prefix = match env::var("SS58_PREFIX") {
+            Ok(prefix) => prefix.parse::<u16>()?,
+            Err(_) => DEFAULT_SS58_PREFIX,
+        };
+        WalletFormat::new(scheme, ss58_prefix)
+    }
+
+    // Applies the scheme and prefix chosen in a request, if any.
+    pub fn with(self, scheme: Option<&str>, ss58_prefix: Option<u16>) -> Result<Self> {
+        let scheme = match scheme {
+            Some(name) => KeyScheme::parse(name)?,
+            None => self.scheme,
+        };
+        WalletFormat::new(scheme, ss58_prefix.unwrap_or(self.ss58_prefix))
+    }
+}
+
+/// A wallet key of any supported scheme. Like `SecretString`, it implements
+/// neither `Debug`, `Clone` nor `Serialize`, and its secret is wiped on drop:
+///
+/// ```compile_fail
+/// use cess_rust_server::controllers::accounts::{get_wallet_pair, KeyScheme};
+///
+/// let pair = get_wallet_pair("bottom drive obey lake curtain smoke basket hold race lonely fit walk", None, KeyScheme::Ed25519).unwrap();
+/// println!("{:?}", pair);
+/// ```
+///
+/// ```compile_fail
+/// use cess_rust_server::controllers::accounts::{get_wallet_pair, KeyScheme};
+///
+/// let pair = get_wallet_pair("bottom drive obey lake curtain smoke basket hold race lonely fit walk", None, KeyScheme::Sr25519).unwrap();
+/// let copy = pair.clone();
+/// ```
+pub enum WalletPair {
+    Sr25519(Pair),
+    // Boxed: an ed25519 pair is several times the size of the others.
+    Ed25519(Box<ed25519::Pair>),
+    Ecdsa(ecdsa::Pair),
+}
+
+// sr25519 and ECDSA pairs wipe their secret themselves when dropped. The
+// ed25519 pair is `Copy` and does not, so it is overwritten in place here.
+impl Drop for WalletPair {
+    fn drop(&mut self) {
+        if let WalletPair::Ed25519(pair) = self {
+            **pair = ed25519::Pair::from_seed(&[0u8; 32]);
+            // Keeps the store from being optimized away as dead.
+            std::hint::black_box(&**pair);
+        }
+    }
+}
+
+impl WalletPair {
+    pub fn scheme(&self) -> KeyScheme {
+        match self {
+            WalletPair::Sr25519(_) => KeyScheme::Sr25519,
+            WalletPair::Ed25519(_) => KeyScheme::Ed25519,
+            WalletPair::Ecdsa(_) => KeyScheme::Ecdsa,
+        }
+    }
+
+    // The on-chain account: the public key itself, or the blake2 hash of a
+    // 33-byte ECDSA public key.
+    pub fn account_id(&self) -> AccountId32 {
+        match self {
+            WalletPair::Sr25519(pair) => AccountId32::new(pair.public().0),
+            WalletPair::Ed25519(pair) => AccountId32::new(pair.public().0),
+            WalletPair::Ecdsa(pair) => AccountId32::new(blake2_256(&pair.public().0)),
+        }
+    }
+
+    // Raw signature bytes, as polkadot-js `signRaw` returns them.
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        match self {
+            WalletPair::Sr25519(pair) => pair.sign(msg).0.to_vec(),
+            WalletPair::Ed25519(pair) => pair.sign(msg).0.to_vec(),
+            WalletPair::Ecdsa(pair) => pair.sign(msg).0.to_vec(),
+        }
+    }
+}
+
+// BIP39: every 3 words carry 32 bits of entropy plus 1 checksum bit.
+fn entropy_len(word_count: usize) -> Result<usize> {
+    match word_count {
+        12 | 15 | 18 | 21 | 24 => Ok(word_count / 3 * 4),
+        _ => bail!("Unsupported mnemonic word count: {}", word_count),
+    }
+}
+
+pub fn parse_language(name: &str) -> Result<Language> {
+    let language = match name.to_lowercase().as_str() {
+        "english" | "en" => Language::English,
+        "chinese_simplified" | "chinese-simplified" | "zh-hans" => Language::SimplifiedChinese,
+        "chinese_traditional" | "chinese-traditional" | "zh-hant" => Language::TraditionalChinese,
+        "czech" | "cs" => Language::Czech,
+        "french" | "fr" => Language::French,
+        "italian" | "it" => Language::Italian,
+        "japanese" | "ja" => Language::Japanese,
+        "korean" | "ko" => Language::Korean,
+        "spanish" | "es" => Language::Spanish,
+        _ => bail!("Unsupported mnemonic language: {}", name),
+    };
+    Ok(language)
+}
+
+pub fn generate_mnemonic(word_count: usize, language: Language) -> Result<SecretString> {
+    let mut entropy = Zeroizing::new(vec![0u8; entropy_len(word_count)?]);
+    OsRng.try_fill_bytes(&mut entropy)?;
+    let mnemonic = Mnemonic::from_entropy_in(language, &entropy)?;
+    Ok(SecretString::from(mnemonic.to_string()))
+}
+
+fn pair_from_secret<P: sp_core_pair>(mnemonic: &str, password_override: Option<&str>) -> Result<P> {
+    // Wallets imported from a keystore have no phrase and are stored as their
+    // `0x` hex secret key, which has no password to apply.
+    if let Some(secret) = mnemonic.strip_prefix("0x") {
+        return match P::from_seed_slice(&Zeroizing::new(hex::decode(secret)?)) {
+            Ok(pair) => Ok(pair),
+            Err(err) => bail!("{:?}", err),
+        };
+    }
+
+    // sp_core only parses English phrases, but Substrate keys are derived from the
+    // entropy alone, so phrases in other languages are re-encoded as English first.
+    let phrase = Zeroizing::new(match Mnemonic::parse_in(Language::English, mnemonic) {
+        Ok(_) => mnemonic.to_string(),
+        Err(_) => {
+            let entropy = Zeroizing::new(Mnemonic::parse(mnemonic)?.to_entropy());
+            Mnemonic::from_entropy(&entropy)?.to_string()
+        },
+    });
+    let pair = P::from_phrase(&phrase, password_override);
 
     match pair {
         Ok(pair) => Ok(pair.0),
-        Err(err) => bail!(err),
+        Err(err) => bail!("{:?}", err),
+    }
+}
+
+pub fn get_pair(mnemonic: &str, password_override: Option<&str>) -> Result<Pair> {
+    pair_from_secret(mnemonic, password_override)
+}
+
+pub fn get_wallet_pair(mnemonic: &str, password_override: Option<&str>, scheme: KeyScheme) -> Result<WalletPair> {
+    let pair = match scheme {
+        KeyScheme::Sr25519 => WalletPair::Sr25519(pair_from_secret(mnemonic, password_override)?),
+        KeyScheme::Ed25519 => WalletPair::Ed25519(Box::new(pair_from_secret(mnemonic, password_override)?)),
+        KeyScheme::Ecdsa => WalletPair::Ecdsa(pair_from_secret(mnemonic, password_override)?),
+    };
+    Ok(pair)
+}
+
+// Whether `phrase` is a BIP39 mnemonic in any supported language, as opposed to
+// a `0x` hex secret key or anything else `get_wallet_pair` would take.
+pub fn is_mnemonic(phrase: &str) -> bool {
+    Mnemonic::parse(phrase).is_ok()
+}
+
+// Account id and SS58 prefix of an address of any network.
+pub fn parse_ss58(address: &str) -> Result<(AccountId32, u16)> {
+    match AccountId32::from_ss58check_with_version(address) {
+        Ok((account_id, format)) => Ok((account_id, format.prefix())),
+        Err(_) => bail!("Error: Invalid SS58 address"),
+    }
+}
+
+pub fn ss58_address(pair: &WalletPair, ss58_prefix: u16) -> String {
+    format_ss58(&pair.account_id(), ss58_prefix)
+}
+
+pub fn format_ss58(account_id: &AccountId32, ss58_prefix: u16) -> String {
+    account_id.to_ss58check_with_version(Ss58AddressFormat::custom(ss58_prefix))
+}
+
+// Parses a Substrate derivation path such as `//savings` or `//0/1`: `//` starts
+// a hard junction and `/` a soft one. Passwords (`///`) are not accepted.
+pub fn parse_derivation_path(path: &str) -> Result<Vec<DeriveJunction>> {
+    if path.len() > MAX_DERIVATION_PATH_LEN {
+        bail!("Derivation path is too long");
+    }
+
+    let mut junctions = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        let (hard, tail) = match rest.strip_prefix("//") {
+            Some(tail) => (true, tail),
+            None => match rest.strip_prefix('/') {
+                Some(tail) => (false, tail),
+                None => bail!("Invalid derivation path: {}", path),
+            },
+        };
+        let end = tail.find('/').unwrap_or(tail.len());
+        let code = &tail[..end];
+        if code.is_empty() {
+            bail!("Invalid derivation path: {}", path);
+        }
+        let junction = DeriveJunction::from(code);
+        junctions.push(if hard { junction.harden() } else { junction });
+        rest = &tail[end..];
+    }
+    if junctions.is_empty() {
+        bail!("Derivation path must not be empty");
+    }
+    Ok(junctions)
+}
+
+fn derive_with<P: sp_core_pair>(pair: &P, derivation_path: &str) -> Result<P> {
+    let junctions = parse_derivation_path(derivation_path)?;
+    match pair.derive(junctions.into_iter(), None) {
+        Ok((derived, _)) => Ok(derived),
+        Err(_) => bail!("Failed to derive {}", derivation_path),
+    }
+}
+
+// ECDSA keys only support hard junctions.
+pub fn derive_pair(pair: &WalletPair, derivation_path: &str) -> Result<WalletPair> {
+    let derived = match pair {
+        WalletPair::Sr25519(pair) => WalletPair::Sr25519(derive_with(pair, derivation_path)?),
+        WalletPair::Ed25519(pair) => WalletPair::Ed25519(Box::new(derive_with(pair.as_ref(), derivation_path)?)),
+        WalletPair::Ecdsa(pair) => WalletPair::Ecdsa(derive_with(pair, derivation_path)?),
+    };
+    Ok(derived)
+}
+
+fn parse_bip32_path(path: &str) -> Result<Vec<u32>> {
+    let mut segments = path.split('/');
+    if segments.next() != Some("m") {
+        bail!("Derivation path must start with `m`: {}", path);
+    }
+    segments
+        .map(|segment| {
+            let (index, hardened) = match segment.strip_suffix('\'') {
+                Some(index) => (index, BIP32_HARDENED),
+                None => (segment, 0),
+            };
+            let index = index.parse::<u32>()?;
+            if index >= BIP32_HARDENED {
+                bail!("Derivation index out of range: {}", segment);
+            }
+            Ok(index | hardened)
+        })
+        .collect()
+}
+
+// BIP32 private key derivation from a BIP39 seed.
+fn derive_bip32(seed: &[u8], path: &str) -> Result<SecretKey> {
+    let secp = Secp256k1::new();
+    let hmac = |key: &[u8], data: &[u8]| -> Result<Zeroizing<[u8; 64]>> {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+        mac.update(data);
+        Ok(Zeroizing::new(mac.finalize().into_bytes().into()))
+    };
+
+    let master = hmac(b"Bitcoin seed", seed)?;
+    let mut key = SecretKey::from_slice(&master[..32])?;
+    let mut chain_code: Zeroizing<[u8; 32]> = Zeroizing::new(master[32..].try_into()?);
+
+    for index in parse_bip32_path(path)? {
+        let mut data = Zeroizing::new(if index >= BIP32_HARDENED {
+            let mut data = vec![0u8];
+            data.extend_from_slice(&key.secret_bytes());
+            data
+        } else {
+            PublicKey::from_secret_key(&secp, &key).serialize().to_vec()
+        });
+        data.extend_from_slice(&index.to_be_bytes());
+
+        let child = hmac(chain_code.as_slice(), &data)?;
+        let tweak = Scalar::from_be_bytes(child[..32].try_into()?)?;
+        key = key.add_tweak(&tweak)?;
+        chain_code = Zeroizing::new(child[32..].try_into()?);
     }
+    Ok(key)
 }
 
-// pub fn verify_signed_msg(signed_msg: &str, msg: &[u8], account_str: &str) -> Result<bool> {
-//     let account_pubkey = sp_keyring::sr25519::sr25519::Public::from_string(account_str)?;
-//     let sign_bytes = match Vec::from_hex(signed_msg) {
-//         Ok(sign_bytes) => sign_bytes,
-//         Err(_) => bail!("Error: Failed to decode signed message"),
-//     };
-//     let signed_msg =
-//         cess_rust_sdk::subxt::ext::sp_core::sr25519::Signature::from_slice(&sign_bytes[..]);
+// The EVM key of a wallet is derived from the standard BIP39 seed of the
+// phrase (empty passphrase) at `EVM_DERIVATION_PATH`.
+pub fn get_evm_key(mnemonic: &str) -> Result<SecretKey> {
+    let seed = Zeroizing::new(Mnemonic::parse(mnemonic)?.to_seed(""));
+    derive_bip32(seed.as_slice(), EVM_DERIVATION_PATH)
+}
+
+// Lowercase `0x` hex address of the wallet's EVM key.
+pub fn get_evm_address(mnemonic: &str) -> Result<String> {
+    let key = get_evm_key(mnemonic)?;
+    Ok(format!("{:?}", SecretKeyRef::new(&key).address()))
+}
+
+// `format` is the one of the account: its scheme, which the address alone does
+// not reveal, and the SS58 prefix the address has to be encoded with.
+pub fn verify_signed_msg(signed_msg: &str, msg: &[u8], account_str: &str, format: WalletFormat) -> Result<bool> {
+    let (account_id, prefix) = parse_ss58(account_str)?;
+    if prefix != format.ss58_prefix {
+        bail!("Error: Address is not in the account's SS58 format");
+    }
+    let account_bytes: &[u8; 32] = account_id.as_ref();
+    let signed_msg = signed_msg.strip_prefix("0x").unwrap_or(signed_msg);
+    let sign_bytes = match hex::decode(signed_msg) {
+        Ok(sign_bytes) => sign_bytes,
+        Err(_) => bail!("Error: Failed to decode signed message"),
+    };
+
+    let verified = match format.scheme {
+        KeyScheme::Sr25519 => Signature::from_slice(&sign_bytes[..])
+            .is_some_and(|sig| Pair::verify(&sig, msg, &Public::from_raw(*account_bytes))),
+        KeyScheme::Ed25519 => ed25519::Signature::from_slice(&sign_bytes[..]).is_some_and(|sig| {
+            ed25519::Pair::verify(&sig, msg, &ed25519::Public::from_raw(*account_bytes))
+        }),
+        // The account is the hash of the public key, so recover the key instead.
+        KeyScheme::Ecdsa => ecdsa::Signature::from_slice(&sign_bytes[..])
+            .and_then(|sig| sig.recover(msg))
+            .is_some_and(|public| blake2_256(&public.0) == *account_bytes),
+    };
+    Ok(verified)
+}
+
+// Wraps a raw payload in `<Bytes>..</Bytes>` unless it already is, like
+// polkadot-js `u8aWrapBytes`, so a signature can never pass for an extrinsic.
+pub fn wrap_bytes(payload: &[u8]) -> Vec<u8> {
+    if payload.starts_with(BYTES_PREFIX) && payload[BYTES_PREFIX.len()..].ends_with(BYTES_SUFFIX) {
+        return payload.to_vec();
+    }
+    [BYTES_PREFIX, payload, BYTES_SUFFIX].concat()
+}
+
+// Scopes are compared and recorded trimmed and lowercase, so `Transfer` cannot
+// slip past a sensitive `transfer`.
+pub fn normalize_scope(scope: &str) -> String {
+    scope.trim().to_lowercase()
+}
+
+/// Limits on what the `/sign` endpoint signs.
+#[derive(Clone, Debug)]
+pub struct SigningPolicy {
+    pub max_payload_len: usize,
+    pub sensitive_scopes: Vec<String>,
+}
+
+impl SigningPolicy {
+    pub fn from_env() -> Result<Self> {
+        dotenv().ok();
+
+        let max_payload_len = match env::var("SIGN_MAX_PAYLOAD_LEN") {
+            Ok(len) => len.parse::<usize>()?,
+            Err(_) => DEFAULT_SIGN_MAX_PAYLOAD_LEN,
+        };
+        let sensitive_scopes = env::var("SIGN_SENSITIVE_SCOPES")
+            .unwrap_or_else(|_| DEFAULT_SIGN_SENSITIVE_SCOPES.to_string())
+            .split(',')
+            .map(normalize_scope)
+            .filter(|scope| !scope.is_empty())
+            .collect();
+
+        Ok(SigningPolicy { max_payload_len, sensitive_scopes })
+    }
 
-//     if let Some(signed_msg) = signed_msg {
-//         if sr25519_verify(&signed_msg, msg, &account_pubkey) {
-//             return Ok(true);
-//         }
-//     }
-//     Ok(false)
-// }
+    // Sensitive scopes need a fresh face match rather than just a token.
+    pub fn is_sensitive(&self, scope: &str) -> bool {
+        self.sensitive_scopes.contains(&normalize_scope(scope))
+    }
+}
 
-pub fn sign_message(msg: &[u8], pair: Pair) -> Result<String> {
+pub fn sign_message(msg: &[u8], pair: &WalletPair) -> Result<String> {
     let signed_msg = pair.sign(msg);
     let hex_string = hex::encode(signed_msg);
     Ok(hex_string)
 }
 
-// pub fn verify_signed_polkadot_msg(
-//     signed_msg: &str,
-//     raw_msg: &str,
-//     account_str: &str,
-// ) -> Result<bool> {
-//     // <Bytes>msg</Bytes>
-//     // In Substrate/Polkadot the <Bytes> was added to prevent someone using a Polkadot wallet as signing oracle;
-//     // its a necessary security measurement.
-//     let msg = format!("<Bytes>{}</Bytes>", raw_msg);
-//     let msg_bytes = msg.as_bytes();
-//     // verify the signed string
-//     // The account auth column has the msg string,
-//     // form.signed_message is signed message send by the user trying to login
-//     if verify_signed_msg(signed_msg, msg_bytes, account_str)? {
-//         return Ok(true);
-//     }
-//     Ok(false)
-// }
-
-pub fn verify_signed_evm_msg(signed_msg: &str, msg: &str, account_str: &str) -> Result<bool> {
+pub fn verify_signed_polkadot_msg(
+    signed_msg: &str,
+    raw_msg: &str,
+    account_str: &str,
+    format: WalletFormat,
+) -> Result<bool> {
+    // <Bytes>msg</Bytes>
+    // In Substrate/Polkadot the <Bytes> was added to prevent someone using a Polkadot wallet as signing oracle;
+    // its a necessary security measurement.
+    let msg = format!("<Bytes>{}</Bytes>", raw_msg);
+    let msg_bytes = msg.as_bytes();
+    // verify the signed string
+    // The auth_challenge row has the msg string,
+    // signed_msg is the signed message sent by the user trying to login
+    if verify_signed_msg(signed_msg, msg_bytes, account_str, format)? {
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Why an EIP-191 signature could not be checked; a well-formed signature by
+/// another key is not an error but `Ok(false)`.
+#[derive(Debug, PartialEq)]
+pub enum EvmSignatureError {
+    InvalidHex,
+    InvalidLength(usize),
+    InvalidRecoveryId(u8),
+    Recovery,
+}
+
+impl fmt::Display for EvmSignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvmSignatureError::InvalidHex => write!(f, "Signature is not valid hex"),
+            EvmSignatureError::InvalidLength(len) => {
+                write!(f, "Signature must be 65 bytes, got {}", len)
+            }
+            EvmSignatureError::InvalidRecoveryId(v) => write!(f, "Invalid recovery id: {}", v),
+            EvmSignatureError::Recovery => write!(f, "Failed to recover the signer"),
+        }
+    }
+}
+
+impl std::error::Error for EvmSignatureError {}
+
+pub fn verify_signed_evm_msg(
+    signed_msg: &str,
+    msg: &str,
+    account_str: &str,
+) -> Result<bool, EvmSignatureError> {
     let message = hash_message(msg.to_string());
-    let signed_msg: &str = if let Some(signed_msg) = signed_msg.strip_prefix("0x") {
-        signed_msg
-    } else {
-        signed_msg
-    };
+    let signed_msg = signed_msg.strip_prefix("0x").unwrap_or(signed_msg);
 
-    let signature = hex::decode(signed_msg).unwrap();
+    let signature = hex::decode(signed_msg).map_err(|_| EvmSignatureError::InvalidHex)?;
+    if signature.len() != 65 {
+        return Err(EvmSignatureError::InvalidLength(signature.len()));
+    }
 
-    let recovery_id = signature[64] as i32 - 27;
-    let pubkey = recover(&message, &signature[..64], recovery_id).unwrap();
+    // Wallets send `v` either as 27/28 or as the raw recovery id 0/1.
+    let recovery_id = match signature[64] {
+        0 | 1 => signature[64] as i32,
+        27 | 28 => signature[64] as i32 - 27,
+        v => return Err(EvmSignatureError::InvalidRecoveryId(v)),
+    };
+    let pubkey = recover(&message, &signature[..64], recovery_id)
+        .map_err(|_| EvmSignatureError::Recovery)?;
     if format!("{:?}", pubkey).to_lowercase() == account_str.to_lowercase() {
         return Ok(true);
     }
@@ -94,3 +540,229 @@ pub fn hash_message(message: String) -> [u8; 32] {
         .as_bytes(),
     )
 }
+
+// EIP-55 mixed-case checksum encoding of a `0x` prefixed hex address.
+pub fn to_checksum_address(account_str: &str) -> Option<String> {
+    let hex_part = account_str.strip_prefix("0x")?;
+    if hex_part.len() != 40 || !hex_part.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let lower = hex_part.to_lowercase();
+    let hash = keccak256(lower.as_bytes());
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = if i % 2 == 0 { hash[i / 2] >> 4 } else { hash[i / 2] & 0x0f };
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    Some(format!("0x{}", checksummed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cess_rust_sdk::core::utils::account::get_pair_address_as_ss58_address;
+
+    #[test]
+    fn test_generate_mnemonic() {
+        let languages = [Language::English, Language::French, Language::Japanese];
+        for language in languages {
+            for word_count in [12, 15, 18, 21, 24] {
+                let phrase = generate_mnemonic(word_count, language).unwrap().expose_secret().to_string();
+                let mnemonic = Mnemonic::parse_in(language, phrase.as_str()).unwrap();
+                assert_eq!(mnemonic.word_count(), word_count);
+                assert_eq!(mnemonic.to_entropy().len(), entropy_len(word_count).unwrap());
+            }
+        }
+        assert!(generate_mnemonic(13, Language::English).is_err());
+    }
+
+    #[test]
+    fn test_get_pair_language_independent() {
+        let french = generate_mnemonic(12, Language::French).unwrap().expose_secret().to_string();
+        let entropy = Mnemonic::parse_in(Language::French, french.as_str()).unwrap().to_entropy();
+        let english = Mnemonic::from_entropy(&entropy).unwrap().to_string();
+
+        let french_pair = get_pair(&french, None).unwrap();
+        let english_pair = get_pair(&english, None).unwrap();
+        assert_eq!(french_pair.public(), english_pair.public());
+    }
+
+    #[test]
+    fn test_is_mnemonic() {
+        let phrase = "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+        let french = generate_mnemonic(12, Language::French).unwrap();
+        assert!(is_mnemonic(phrase));
+        assert!(is_mnemonic(french.expose_secret()));
+
+        // Accepted by `get_wallet_pair`, but not a mnemonic.
+        let seed = format!("0x{}", "11".repeat(32));
+        assert!(get_wallet_pair(&seed, None, KeyScheme::Sr25519).is_ok());
+        assert!(!is_mnemonic(&seed));
+        assert!(!is_mnemonic("bottom drive obey lake"));
+    }
+
+    #[test]
+    fn test_parse_ss58() {
+        let (account_id, prefix) = parse_ss58("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY").unwrap();
+        assert_eq!(prefix, 42);
+
+        let cess_address = format_ss58(&account_id, 11330);
+        assert_eq!(parse_ss58(&cess_address).unwrap(), (account_id, 11330));
+        assert!(parse_ss58("not an address").is_err());
+    }
+
+    #[test]
+    fn test_derive_pair() {
+        let phrase = generate_mnemonic(12, Language::English).unwrap().expose_secret().to_string();
+        let pair = get_wallet_pair(&phrase, None, KeyScheme::Sr25519).unwrap();
+
+        for path in ["//savings", "//0", "/soft", "//hard/soft//0"] {
+            let expected = Pair::from_string(&format!("{}{}", phrase, path), None).unwrap();
+            assert_eq!(derive_pair(&pair, path).unwrap().account_id(), AccountId32::new(expected.public().0));
+        }
+        assert_ne!(derive_pair(&pair, "//0").unwrap().account_id(), derive_pair(&pair, "/0").unwrap().account_id());
+
+        for path in ["", "savings", "//", "//a///password", "//a//"] {
+            assert!(derive_pair(&pair, path).is_err(), "{}", path);
+        }
+
+        let ecdsa_pair = get_wallet_pair(&phrase, None, KeyScheme::Ecdsa).unwrap();
+        assert!(derive_pair(&ecdsa_pair, "//0").is_ok());
+        assert!(derive_pair(&ecdsa_pair, "/0").is_err());
+    }
+
+    #[test]
+    fn test_verify_signed_polkadot_msg() {
+        let phrase = generate_mnemonic(12, Language::English).unwrap().expose_secret().to_string();
+        let pair = get_wallet_pair(&phrase, None, KeyScheme::Sr25519).unwrap();
+        let account_str = get_pair_address_as_ss58_address(get_pair(&phrase, None).unwrap()).unwrap();
+        assert_eq!(account_str, ss58_address(&pair, DEFAULT_SS58_PREFIX));
+        let signed = sign_message(b"<Bytes>nonce</Bytes>", &pair).unwrap();
+        let format = WalletFormat::new(KeyScheme::Sr25519, DEFAULT_SS58_PREFIX).unwrap();
+
+        assert!(verify_signed_polkadot_msg(&signed, "nonce", &account_str, format).unwrap());
+        assert!(!verify_signed_polkadot_msg(&signed, "other", &account_str, format).unwrap());
+        // signing the bare challenge must not be accepted
+        let unwrapped = sign_message(b"nonce", &pair).unwrap();
+        assert!(!verify_signed_polkadot_msg(&unwrapped, "nonce", &account_str, format).unwrap());
+        assert!(verify_signed_polkadot_msg("zz", "nonce", &account_str, format).is_err());
+        // the same key under another network's prefix is not the account's address
+        assert!(verify_signed_polkadot_msg(&signed, "nonce", &ss58_address(&pair, 42), format).is_err());
+    }
+
+    #[test]
+    fn test_wrap_bytes() {
+        assert_eq!(wrap_bytes(b"nonce"), b"<Bytes>nonce</Bytes>".to_vec());
+        assert_eq!(wrap_bytes(b"<Bytes>nonce</Bytes>"), b"<Bytes>nonce</Bytes>".to_vec());
+        assert_eq!(wrap_bytes(b"<Bytes>nonce"), b"<Bytes><Bytes>nonce</Bytes>".to_vec());
+        assert_eq!(wrap_bytes(b"<Bytes>"), b"<Bytes><Bytes></Bytes>".to_vec());
+
+        let pair = get_wallet_pair(generate_mnemonic(12, Language::English).unwrap().expose_secret(), None, KeyScheme::Sr25519).unwrap();
+        let signed = sign_message(&wrap_bytes(b"nonce"), &pair).unwrap();
+        let format = WalletFormat::new(KeyScheme::Sr25519, DEFAULT_SS58_PREFIX).unwrap();
+        assert!(verify_signed_polkadot_msg(&signed, "nonce", &ss58_address(&pair, DEFAULT_SS58_PREFIX), format).unwrap());
+    }
+
+    #[test]
+    fn test_signing_policy() {
+        let policy = SigningPolicy {
+            max_payload_len: DEFAULT_SIGN_MAX_PAYLOAD_LEN,
+            sensitive_scopes: vec![normalize_scope(" Transfer ")],
+        };
+        assert!(policy.is_sensitive("transfer"));
+        assert!(policy.is_sensitive("TRANSFER"));
+        assert!(policy.is_sensitive(" transfer\t"));
+        assert!(!policy.is_sensitive("transfers"));
+        assert!(!policy.is_sensitive("login"));
+    }
+
+    #[test]
+    fn test_key_schemes() {
+        let phrase = generate_mnemonic(12, Language::English).unwrap().expose_secret().to_string();
+        for scheme in [KeyScheme::Sr25519, KeyScheme::Ed25519, KeyScheme::Ecdsa] {
+            let pair = get_wallet_pair(&phrase, None, scheme).unwrap();
+            assert_eq!(KeyScheme::parse(scheme.as_str()).unwrap(), scheme);
+
+            // prefix 42 is the generic Substrate format, whose addresses start with `5`
+            let account_str = ss58_address(&pair, 42);
+            assert!(account_str.starts_with('5'));
+            assert_ne!(account_str, ss58_address(&pair, DEFAULT_SS58_PREFIX));
+
+            let signed = sign_message(b"<Bytes>nonce</Bytes>", &pair).unwrap();
+            let format = WalletFormat::new(scheme, 42).unwrap();
+            assert!(verify_signed_polkadot_msg(&signed, "nonce", &account_str, format).unwrap());
+            assert!(!verify_signed_polkadot_msg(&signed, "other", &account_str, format).unwrap());
+        }
+
+        let sr25519 = get_wallet_pair(&phrase, None, KeyScheme::Sr25519).unwrap();
+        let ed25519 = get_wallet_pair(&phrase, None, KeyScheme::Ed25519).unwrap();
+        assert_ne!(sr25519.account_id(), ed25519.account_id());
+        let signed = sign_message(b"<Bytes>nonce</Bytes>", &ed25519).unwrap();
+        let format = WalletFormat::new(KeyScheme::Sr25519, 42).unwrap();
+        assert!(!verify_signed_polkadot_msg(&signed, "nonce", &ss58_address(&ed25519, 42), format).unwrap());
+
+        assert!(KeyScheme::parse("rsa").is_err());
+        assert!(WalletFormat::new(KeyScheme::Sr25519, 46).is_err());
+        assert!(WalletFormat::new(KeyScheme::Sr25519, 0x4000).is_err());
+        let format = WalletFormat::new(KeyScheme::Sr25519, DEFAULT_SS58_PREFIX).unwrap();
+        assert_eq!(format.with(Some("ecdsa"), Some(0)).unwrap(), WalletFormat::new(KeyScheme::Ecdsa, 0).unwrap());
+    }
+
+    #[test]
+    fn test_verify_signed_evm_msg() {
+        let key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let account_str = format!("{:?}", SecretKeyRef::new(&key).address());
+        let signature = SecretKeyRef::new(&key)
+            .sign(&hash_message("hello".to_string()), None)
+            .unwrap();
+        let mut bytes = signature.r.as_bytes().to_vec();
+        bytes.extend_from_slice(signature.s.as_bytes());
+        bytes.push(signature.v as u8);
+        let signed = format!("0x{}", hex::encode(&bytes));
+
+        assert_eq!(verify_signed_evm_msg(&signed, "hello", &account_str), Ok(true));
+        assert_eq!(verify_signed_evm_msg(&signed, "hello!", &account_str), Ok(false));
+
+        bytes[64] -= 27;
+        assert_eq!(verify_signed_evm_msg(&hex::encode(&bytes), "hello", &account_str), Ok(true));
+        bytes[64] = 5;
+        assert_eq!(
+            verify_signed_evm_msg(&hex::encode(&bytes), "hello", &account_str),
+            Err(EvmSignatureError::InvalidRecoveryId(5))
+        );
+        assert_eq!(
+            verify_signed_evm_msg(&hex::encode(&bytes[..64]), "hello", &account_str),
+            Err(EvmSignatureError::InvalidLength(64))
+        );
+        assert_eq!(
+            verify_signed_evm_msg("0xzz", "hello", &account_str),
+            Err(EvmSignatureError::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn test_get_evm_address() {
+        // BIP39 test mnemonic; the address matches MetaMask's first account.
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let evm_address = get_evm_address(phrase).unwrap();
+        assert_eq!(
+            to_checksum_address(&evm_address).unwrap(),
+            "0x9858EfFD232B4033E47d90003D41EC34EcaEda94"
+        );
+        assert!(parse_bip32_path("44'/60'").is_err());
+    }
+
+    #[test]
+    fn test_to_checksum_address() {
+        let expected = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        assert_eq!(to_checksum_address(&expected.to_lowercase()).as_deref(), Some(expected));
+        assert_eq!(to_checksum_address("0x1234"), None);
+    }
+}
@@ -1,231 +1,3156 @@
-use actix_web::{web, HttpResponse, Responder};
-use cess_rust_sdk::core::utils::account::get_pair_address_as_ss58_address;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use cess_rust_sdk::subxt::ext::sp_core::hashing::blake2_256;
 use serde::{Deserialize, Serialize};
-use sp_keyring::sr25519::sr25519::Pair;
 use diesel::prelude::*;
+use dotenvy::dotenv;
+use log::error;
+use std::env;
+use subtle::ConstantTimeEq;
 
 use crate::{
-    controllers::accounts::{generate_mnemonic, get_pair},
+    backup::{combine_shares, encrypt_share, split_secret, Share},
+    controllers::accounts::{
+        format_ss58, generate_mnemonic, get_evm_address, get_wallet_pair, is_mnemonic, normalize_scope,
+        parse_language, parse_ss58, ss58_address, to_checksum_address, verify_signed_evm_msg,
+        verify_signed_polkadot_msg, wrap_bytes,
+        KeyScheme, SigningPolicy, WalletFormat, WalletPair, DEFAULT_MNEMONIC_WORD_COUNT,
+    },
     databases::*,
-    databases::models::Account,
+    databases::models::{Account, KeyRotation, NewAccountData, NewSignatureAudit, Session, SubAccount},
+    face::{
+        best_match, score_template, verify, DuplicateConfig, DuplicatePolicy, MatchConfig,
+        protection::{new_seed, project, ProtectionConfig, ProtectionMode},
+        template::FaceTemplate,
+    },
     schema::account::dsl::*,
-    jwt::generate_token
+    rotation::{sweep_progress, SweepProgress},
+    secret::{expose_secret, expose_secrets, SecretString},
+    jwt::{
+        hash_refresh_token, issue_token, middleware::{AuthenticatedWallet, OptionalWallet},
+        IssuedToken, SCOPE_WALLET
+    },
+    keystore::{decrypt_keystore, secret_seed, Keystore, MIN_PASSWORD_LEN},
+    siwe::{SiweConfig, SiweMessage},
+    utils::{
+        authorize_account_to_upload_to_gateway, create_bucket, generate_code, sweep_balance, user_buckets
+    },
+    vault::{KeySlot, KeyVault}
 };
 
+const LOG_TARGET: &str = "Controllers";
+
+const DEFAULT_REVEAL_TICKET_TTL: i64 = 120;
+const DEFAULT_REVEAL_COOLDOWN: i64 = 86400;
+const DEFAULT_AUTH_CHALLENGE_TTL: i64 = 300;
+const DEFAULT_SIWE_NONCE_TTL: i64 = 300;
+const DEFAULT_IMPORT_CHALLENGE_TTL: i64 = 300;
+const DEFAULT_REFRESH_TOKEN_TTL: i64 = 2592000;
+// A pending rotation still on the old key after this long was interrupted.
+const ABANDONED_ROTATION_MINUTES: i64 = 10;
+const DEFAULT_SIGN_SCOPE: &str = "general";
+const MAX_SIGN_SCOPE_LEN: usize = 64;
+const MAX_SUB_ACCOUNT_NAME_LEN: usize = 64;
+const MAX_AUDIT_IP_LEN: usize = 64;
+const MAX_AUDIT_USER_AGENT_LEN: usize = 256;
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct GetWalletInfo {
     uid: i64,
     address: String,
+    #[serde(default)]
+    feature: Option<FaceTemplate>
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct CreateWalletInfo {
     uid: i64,
-    feature: Vec<u8>
+    feature: FaceTemplate,
+    #[serde(default)]
+    word_count: Option<usize>,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    key_scheme: Option<String>,
+    #[serde(default)]
+    ss58_prefix: Option<u16>,
+    #[serde(default)]
+    backup: Option<BackupInfo>
+}
+
+// Splits the new mnemonic into `shares` Shamir shares, `threshold` of which
+// recover it. With `guardians`, share i is encrypted to guardian key i.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct BackupInfo {
+    threshold: u8,
+    shares: u8,
+    #[serde(default)]
+    guardians: Vec<String>
+}
+
+// Requests carrying secrets are only `Deserialize`, see `secret::SecretString`.
+#[derive(Deserialize)]
+pub struct RecoverSharesInfo {
+    uid: i64,
+    feature: FaceTemplate,
+    shares: Vec<SecretString>
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RecoverWalletInfo {
+    uid: i64,
+    feature: FaceTemplate,
+    recover_key: String
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct IdentifyInfo {
+    feature: FaceTemplate
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ReissueTemplateInfo {
+    uid: i64,
+    address: String,
+    feature: FaceTemplate
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RevealTicketInfo {
+    uid: i64,
+    address: String,
+    feature: FaceTemplate
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SubAccountInfo {
+    uid: i64,
+    address: String,
+    #[serde(default)]
+    feature: Option<FaceTemplate>,
+    name: String,
+    derivation_path: String
+}
+
+#[derive(Deserialize)]
+pub struct ExportKeystoreInfo {
+    uid: i64,
+    address: String,
+    feature: FaceTemplate,
+    password: SecretString,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    sub_account: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct ImportChallengeInfo {
+    address: String
+}
+
+// A keystore import needs the same ownership proof as one through
+// `/import_wallet`.
+#[derive(Deserialize)]
+pub struct ImportKeystoreInfo {
+    uid: i64,
+    feature: FaceTemplate,
+    keystore: Keystore,
+    password: SecretString,
+    challenge: String,
+    signature: String,
+    #[serde(default)]
+    ss58_prefix: Option<u16>
+}
+
+// Either `mnemonic`, or `keystore` and its `password` together with a
+// `signature` over an issued import `challenge`.
+#[derive(Deserialize)]
+pub struct ImportWalletInfo {
+    uid: i64,
+    feature: FaceTemplate,
+    #[serde(default)]
+    mnemonic: Option<SecretString>,
+    #[serde(default)]
+    keystore: Option<Keystore>,
+    #[serde(default)]
+    password: Option<SecretString>,
+    #[serde(default)]
+    challenge: Option<String>,
+    #[serde(default)]
+    signature: Option<String>,
+    #[serde(default)]
+    key_scheme: Option<String>,
+    #[serde(default)]
+    ss58_prefix: Option<u16>
+}
+
+impl From<ImportKeystoreInfo> for ImportWalletInfo {
+    fn from(info: ImportKeystoreInfo) -> Self {
+        ImportWalletInfo {
+            uid: info.uid,
+            feature: info.feature,
+            mnemonic: None,
+            keystore: Some(info.keystore),
+            password: Some(info.password),
+            challenge: Some(info.challenge),
+            signature: Some(info.signature),
+            key_scheme: Some(KeyScheme::Sr25519.as_str().to_string()),
+            ss58_prefix: info.ss58_prefix
+        }
+    }
+}
+
+// `payload` is UTF-8 text, or hex when `encoding` is `hex`. It is always
+// signed wrapped in `<Bytes>..</Bytes>`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SignInfo {
+    uid: i64,
+    address: String,
+    payload: String,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(default)]
+    feature: Option<FaceTemplate>,
+    #[serde(default)]
+    sub_account: Option<String>
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RotateKeyInfo {
+    uid: i64,
+    address: String,
+    feature: FaceTemplate,
+    #[serde(default)]
+    word_count: Option<usize>,
+    #[serde(default)]
+    language: Option<String>
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RevealMnemonicInfo {
+    ticket: String
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AuthChallengeInfo {
+    address: String
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AuthVerifyInfo {
+    address: String,
+    challenge: String,
+    signature: String
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RevokeSessionInfo {
+    session_id: i64
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenInfo {
+    refresh_token: SecretString
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SiweVerifyInfo {
+    message: String,
+    signature: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct WalletResponse {
+    result: String,
+    msg: String,
+    wallet_address: String,
+    token: String,
+    refresh_token: String
+}
+
+// A wallet response that also carries the EIP-55 address of the wallet's EVM key.
+#[derive(Serialize, Debug)]
+pub struct LinkedWalletResponse {
+    #[serde(flatten)]
+    wallet: WalletResponse,
+    evm_wallet_address: String
+}
+
+#[derive(Serialize)]
+pub struct CreateWalletResponse {
+    #[serde(flatten)]
+    wallet: WalletResponse,
+    evm_wallet_address: String,
+    // Only disclosed to requests carrying the admin key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conflict_account_id: Option<i64>,
+    // Never stored; the caller has to hand them out.
+    #[serde(skip_serializing_if = "Vec::is_empty", serialize_with = "expose_secrets")]
+    backup_shares: Vec<SecretString>
+}
+
+#[derive(Serialize, Debug)]
+pub struct IdentifyResponse {
+    result: String,
+    msg: String,
+    matched: bool,
+    wallet_address: String,
+    uid: i64,
+    // Only reported for a match: the score of a miss would let a probe be
+    // tuned until it passes.
+    score: Option<f32>,
+    threshold: f32,
+    metric: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct RevealTicketResponse {
+    result: String,
+    msg: String,
+    ticket: String,
+    expires_in: i64
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuthChallengeResponse {
+    result: String,
+    msg: String,
+    challenge: String,
+    expires_in: i64
+}
+
+#[derive(Serialize, Debug)]
+pub struct SiweNonceResponse {
+    result: String,
+    msg: String,
+    nonce: String,
+    expires_in: i64
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubAccountEntry {
+    name: String,
+    derivation_path: String,
+    wallet_address: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubAccountResponse {
+    result: String,
+    msg: String,
+    sub_accounts: Vec<SubAccountEntry>
+}
+
+#[derive(Serialize, Debug)]
+pub struct KeystoreResponse {
+    result: String,
+    msg: String,
+    keystore: Option<Keystore>
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignResponse {
+    result: String,
+    msg: String,
+    signature: String,
+    signer: String,
+    payload_hash: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct RotateKeyResponse {
+    #[serde(flatten)]
+    wallet: WalletResponse,
+    evm_wallet_address: String,
+    old_address: String,
+    swept: bool,
+    gateway_authorized: bool,
+    migrated_buckets: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct RevealMnemonicResponse {
+    result: String,
+    msg: String,
+    #[serde(serialize_with = "expose_secret")]
+    mnemonic: SecretString
+}
+
+#[derive(Serialize, Debug)]
+pub struct SessionEntry {
+    id: i64,
+    device: String,
+    ip: String,
+    created_at: i64,
+    expires_at: i64,
+    // Whether this is the session of the requesting token.
+    current: bool
+}
+
+#[derive(Serialize, Debug)]
+pub struct SessionResponse {
+    result: String,
+    msg: String,
+    sessions: Vec<SessionEntry>
+}
+
+#[derive(Serialize, Debug)]
+pub struct WalletSessionResponse {
+    result: String,
+    msg: String,
+    uid: i64,
+    wallet_address: String,
+    evm_wallet_address: String,
+    scopes: Vec<String>
+}
+
+impl From<SubAccount> for SubAccountEntry {
+    fn from(sub: SubAccount) -> Self {
+        SubAccountEntry {
+            name: sub.name,
+            derivation_path: sub.derivation_path,
+            wallet_address: sub.address
+        }
+    }
+}
+
+fn session_entry(listed: Session, current: &Session) -> SessionEntry {
+    SessionEntry {
+        id: listed.id,
+        current: listed.family_id == current.family_id,
+        device: listed.device.unwrap_or_default(),
+        ip: listed.ip.unwrap_or_default(),
+        created_at: listed.created_at.and_utc().timestamp(),
+        expires_at: listed.expires_at.and_utc().timestamp()
+    }
+}
+
+fn checksummed_evm_address(account_data: &Account) -> String {
+    account_data
+        .evm_address
+        .as_deref()
+        .and_then(to_checksum_address)
+        .unwrap_or_default()
+}
+
+fn env_seconds(key: &str, default: i64) -> i64 {
+    dotenv().ok();
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(default)
+}
+
+// Where a request came from, as recorded in the audit logs.
+struct RequestOrigin {
+    ip: Option<String>,
+    user_agent: Option<String>
+}
+
+// The IP is the peer address of the connection: `Forwarded` and
+// `X-Forwarded-For` are set by the client as it likes. Both values are cut to
+// the length of the audit columns, counted in characters like Postgres does.
+fn request_origin(req: &HttpRequest) -> RequestOrigin {
+    let truncate = |value: &str, max_len: usize| value.chars().take(max_len).collect::<String>();
+    RequestOrigin {
+        ip: req.peer_addr().map(|peer| truncate(&peer.ip().to_string(), MAX_AUDIT_IP_LEN)),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| truncate(value, MAX_AUDIT_USER_AGENT_LEN))
+    }
+}
+
+// Issues a refresh token of a token family for the account and stores its hash.
+fn issue_refresh_token(connection: &mut PgConnection, account_id: i64, family_id: &str) -> String {
+    let refresh = generate_code(64);
+    let ttl = env_seconds("REFRESH_TOKEN_TTL", DEFAULT_REFRESH_TOKEN_TTL);
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(ttl);
+    create_refresh_token(connection, account_id, family_id, &hash_refresh_token(&refresh), expires_at);
+    refresh
+}
+
+// Records an issued access token as a session, with the device and IP it was
+// issued to, and pairs it with a refresh token. A login starts a new token
+// family; a refresh continues the family of the token it replaces. Returns the
+// access token and the refresh token.
+fn open_session(connection: &mut PgConnection, req: &HttpRequest, account_id: i64, issued: IssuedToken, family_id: Option<&str>) -> anyhow::Result<(String, String)> {
+    let family = family_id.map_or_else(|| generate_code(32), str::to_string);
+    let origin = request_origin(req);
+    let expires_at = DateTime::from_timestamp(issued.exp as i64, 0)
+        .map(|t| t.naive_utc())
+        .unwrap_or_default();
+    create_session(connection, account_id, &issued.jti, &family, origin.user_agent.as_deref(), origin.ip.as_deref(), expires_at)?;
+    Ok((issued.token, issue_refresh_token(connection, account_id, &family)))
+}
+
+// Verifies a probe against the row's stored template and records the score.
+// Returns `None` when the account has no template enrolled.
+fn match_account(connection: &mut PgConnection, account_data: &Account, probe: &FaceTemplate) -> anyhow::Result<Option<(bool, f32)>> {
+    let keyring = feature_keyring()?;
+    let stored = match decrypt_feature(&keyring, account_data)? {
+        Some(t) => t,
+        None => return Ok(None)
+    };
+    let seed = decrypt_template_seed(&keyring, account_data)?;
+    let (accepted, score) = verify(probe, &stored, seed.as_deref(), &MatchConfig::from_env()?)?;
+    update_match_score(connection, account_data.id, score);
+    Ok(Some((accepted, score)))
+}
+
+// Scans the templates enrolled with the probe's model (and legacy ones of unknown
+// model) and returns the closest account to `probe`, other than `exclude`. A
+// template that does not decrypt is logged and left out rather than failing the
+// whole scan.
+fn closest_account(connection: &mut PgConnection, probe: &FaceTemplate, config: &MatchConfig, exclude: Option<i64>) -> anyhow::Result<Option<(Account, f32)>> {
+    let keyring = feature_keyring()?;
+    let results = account
+        .filter(feature.is_not_null())
+        .filter(feature_model.eq(&probe.model_id).or(feature_model.is_null()))
+        .load::<Account>(connection)?;
+
+    let scored = results.into_iter().filter(|row| Some(row.id) != exclude).filter_map(|row| {
+        let decrypted = decrypt_feature(&keyring, &row)
+            .and_then(|stored| Ok((stored, decrypt_template_seed(&keyring, &row)?)));
+        let (stored, seed) = match decrypted {
+            Ok((stored, seed)) => (stored?, seed),
+            Err(e) => {
+                error!(target: LOG_TARGET, "Failed to decrypt the face template of account {}: {:?}", row.id, e);
+                return None;
+            }
+        };
+        let score = score_template(probe, &stored, seed.as_deref(), config).ok()?;
+        Some((row, score))
+    });
+    Ok(best_match(scored, config.metric))
+}
+
+// Returns the configured duplicate policy and, unless it is `Allow`, the id of
+// an enrolled account other than `exclude` whose template is within the
+// duplicate threshold.
+fn duplicate_enrollment(connection: &mut PgConnection, probe: &FaceTemplate, exclude: Option<i64>) -> anyhow::Result<(DuplicatePolicy, Option<i64>)> {
+    let config = MatchConfig::from_env()?;
+    let duplicate = DuplicateConfig::from_env(&config)?;
+    if duplicate.policy == DuplicatePolicy::Allow {
+        return Ok((duplicate.policy, None));
+    }
+
+    let conflict = closest_account(connection, probe, &config, exclude)?
+        .filter(|(_, score)| duplicate.is_duplicate(config.metric, *score))
+        .map(|(row, _)| row.id);
+    Ok((duplicate.policy, conflict))
+}
+
+// The key is compared in constant time, so response timing does not reveal how
+// much of a guess was right.
+fn is_admin(req: &HttpRequest) -> bool {
+    dotenv().ok();
+    let admin_key = match env::var("ADMIN_API_KEY") {
+        Ok(t) if !t.is_empty() => t,
+        _ => return false
+    };
+    req.headers()
+        .get("X-Admin-Key")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| bool::from(value.as_bytes().ct_eq(admin_key.as_bytes())))
+}
+
+// Either a fresh face match or a wallet-scoped bearer token issued for this
+// very row.
+fn wallet_authorized(connection: &mut PgConnection, wallet: Option<&AuthenticatedWallet>, account_data: &Account, probe: Option<&FaceTemplate>) -> bool {
+    if let Some(probe) = probe {
+        face_matches(connection, account_data, probe)
+    } else if let Some(wallet) = wallet {
+        wallet.has_scope(SCOPE_WALLET)
+            && wallet.uid == account_data.uid
+            && account_data.address.as_deref() == Some(wallet.address.as_str())
+    } else {
+        false
+    }
+}
+
+// Key scheme and SS58 prefix recorded on the account row.
+fn wallet_format(account_data: &Account) -> anyhow::Result<WalletFormat> {
+    let prefix = u16::try_from(account_data.ss58_prefix)?;
+    WalletFormat::new(KeyScheme::parse(&account_data.key_scheme)?, prefix)
+}
+
+// Address of the wallet, or of one of its sub-accounts when `derivation_path`
+// is given, as derived by the key vault.
+fn wallet_address(vault: &dyn KeyVault, account_data: &Account, derivation_path: Option<&str>) -> anyhow::Result<String> {
+    let format = wallet_format(account_data)?;
+    let account_id = vault.derive(KeySlot::Account(account_data.id), format.scheme, derivation_path)?;
+    Ok(format_ss58(&account_id, format.ss58_prefix))
+}
+
+// Hands the key of a newly created account to the vault, removing the row
+// again when the key could not be stored.
+fn store_wallet_key(connection: &mut PgConnection, vault: &dyn KeyVault, created: Account, secret: &SecretString) -> anyhow::Result<Account> {
+    match vault.store(KeySlot::Account(created.id), secret) {
+        Ok(()) => Ok(created),
+        Err(e) => {
+            delete_account(connection, created.id);
+            Err(e)
+        }
+    }
+}
+
+fn face_matches(connection: &mut PgConnection, account_data: &Account, probe: &FaceTemplate) -> bool {
+    matches!(match_account(connection, account_data, probe), Ok(Some((true, _))))
+}
+
+// Builds the template to store for a new enrollment: the probe itself, or a
+// seeded random projection of it when template protection is enabled.
+fn enrollment_template(probe: &FaceTemplate) -> anyhow::Result<(FaceTemplate, Option<Vec<u8>>)> {
+    let protection = ProtectionConfig::from_env()?;
+    match protection.mode {
+        ProtectionMode::None => Ok((probe.clone(), None)),
+        ProtectionMode::RandomProjection => {
+            let seed = new_seed()?;
+            let out_dim = protection.output_dim(probe.dim())?;
+            let protected = project(probe.values(), &seed, out_dim)?;
+            Ok((FaceTemplate::from_f32(&probe.model_id, false, &protected)?, Some(seed.to_vec())))
+        }
+    }
+}
+
+// A key the user already owns, to be enrolled under `probe`. `secret` is a
+// mnemonic or a `0x` hex secret key, as told by `kind`.
+struct ExistingWallet<'a> {
+    user_id: i64,
+    probe: &'a FaceTemplate,
+    secret: &'a SecretString,
+    kind: &'a str,
+    format: WalletFormat,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct RecoverWalletInfo {
-    uid: i64,
-    feature: Vec<u8>,
-    recover_key: String
-}
+// Enrolls a key the user already owns under a new face. The secret is stored
+// like a generated mnemonic.
+fn enroll_existing_wallet(connection: &mut PgConnection, vault: &dyn KeyVault, req: &HttpRequest, wallet: ExistingWallet) -> HttpResponse {
+    let ExistingWallet { user_id, probe, secret, kind, format } = wallet;
+    let (imported_address, imported_key) = match get_wallet_pair(secret.expose_secret(), None, format.scheme) {
+        Ok(pair) => (ss58_address(&pair, format.ss58_prefix), <[u8; 32]>::from(pair.account_id())),
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Invalid mnemonic or key".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    // The same key under another SS58 prefix is the same wallet.
+    let enrolled = account
+        .filter(public_key.eq(imported_key.as_slice()))
+        .count()
+        .get_result::<i64>(connection)
+        .expect("Error loading account");
+    if enrolled > 0 {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Wallet is already enrolled".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let (duplicate_policy, conflict) = match duplicate_enrollment(connection, probe, None) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Invalid face feature".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    let conflict_account_id = conflict.filter(|_| is_admin(req));
+    if duplicate_policy == DuplicatePolicy::Reject && conflict.is_some() {
+        let response_message = CreateWalletResponse {
+            wallet: WalletResponse {
+                result: "Error".to_string(),
+                msg: "Face is already enrolled".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            },
+            evm_wallet_address: "".to_string(),
+            conflict_account_id,
+            backup_shares: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    // Only a mnemonic carries the BIP39 seed the EVM key is derived from.
+    let linked_evm_address = get_evm_address(secret.expose_secret()).ok();
+    let issued = match issue_token(imported_address.clone(), user_id, &[SCOPE_WALLET]) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    let created = enrollment_template(probe)
+        .and_then(|(template, seed)| {
+            create_account(connection, &NewAccountData {
+                uid: user_id,
+                address: Some(&imported_address),
+                evm_address: linked_evm_address.as_deref(),
+                token: Some(&issued.token),
+                feature: Some(&template),
+                template_seed: seed.as_deref(),
+                key_scheme: format.scheme.as_str(),
+                ss58_prefix: format.ss58_prefix as i32,
+                secret_kind: kind,
+            })
+        })
+        .and_then(|created| store_wallet_key(connection, vault, created, secret));
+    let myaccount = match created {
+        Ok(t) => t,
+        // Another request enrolled the same address since the check above.
+        Err(e) if is_unique_violation(&e) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Wallet is already enrolled".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `create_account`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    if let (DuplicatePolicy::Flag, Some(conflict_id)) = (duplicate_policy, conflict) {
+        flag_duplicate_enrollment(connection, myaccount.id, conflict_id);
+    }
+
+    let (jtoken, refresh) = match open_session(connection, req, myaccount.id, issued, None) {
+        Ok(t) => t,
+        Err(e) => {
+            error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `open_session`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    let response_message = CreateWalletResponse {
+        wallet: WalletResponse {
+            result: "Success".to_string(),
+            msg: "Imported wallet successfully".to_string(),
+            wallet_address: imported_address,
+            token: jtoken,
+            refresh_token: refresh,
+        },
+        evm_wallet_address: checksummed_evm_address(&myaccount),
+        conflict_account_id,
+        backup_shares: vec![]
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Encoded Shamir shares of `secret`, each encrypted to its guardian if any.
+fn backup_shares(secret: &SecretString, backup: &BackupInfo) -> anyhow::Result<Vec<SecretString>> {
+    if !backup.guardians.is_empty() && backup.guardians.len() != backup.shares as usize {
+        anyhow::bail!("Expected one guardian key per share");
+    }
+    let shares = split_secret(secret.expose_secret().as_bytes(), backup.threshold, backup.shares)?;
+    if backup.guardians.is_empty() {
+        return Ok(shares.iter().map(|share| SecretString::from(share.encode())).collect());
+    }
+    shares
+        .iter()
+        .zip(&backup.guardians)
+        .map(|(share, guardian)| encrypt_share(share, guardian).map(SecretString::from))
+        .collect()
+}
+
+// Generates the replacement key of a wallet. The old key is copied to the
+// rotation's slot in the vault before the new one replaces it, and the
+// rotation is dropped again if either step fails.
+fn rotate_wallet(connection: &mut PgConnection, vault: &dyn KeyVault, account_data: &Account, format: WalletFormat, info: &RotateKeyInfo) -> anyhow::Result<KeyRotation> {
+    let language = parse_language(info.language.as_deref().unwrap_or("english"))?;
+    let phrase = generate_mnemonic(info.word_count.unwrap_or(DEFAULT_MNEMONIC_WORD_COUNT), language)?;
+    let pair = get_wallet_pair(phrase.expose_secret(), None, format.scheme)?;
+
+    let rotation = create_key_rotation(connection, account_data, &ss58_address(&pair, format.ss58_prefix))?;
+    let stored = vault
+        .copy(KeySlot::Account(account_data.id), KeySlot::Rotation(rotation.id))
+        .and_then(|_| vault.store(KeySlot::Account(account_data.id), &phrase));
+    if let Err(e) = stored {
+        vault.delete(KeySlot::Rotation(rotation.id))?;
+        delete_key_rotation(connection, rotation.id);
+        return Err(e);
+    }
+    Ok(rotation)
+}
+
+// Pending rotation of a wallet whose new key is in the vault. A rotation that
+// was interrupted before that still leaves the old key under the account; once
+// it is old enough not to be running any more, it is dropped and the wallet can
+// be rotated again. A younger one is left alone, and `create_key_rotation`
+// refuses to start another.
+fn pending_rotation(connection: &mut PgConnection, vault: &dyn KeyVault, account_data: &Account) -> anyhow::Result<Option<KeyRotation>> {
+    let rotation = match pending_key_rotation(connection, account_data.id) {
+        Some(t) => t,
+        None => return Ok(None)
+    };
+    let abandoned_before = Utc::now().naive_utc() - Duration::minutes(ABANDONED_ROTATION_MINUTES);
+
+    enum Pending { Resume, Running, Dropped }
+    // Decided under the row lock, so a concurrent retry cannot pick the
+    // rotation up while it is being dropped.
+    let pending = connection.transaction::<_, anyhow::Error, _>(|conn| {
+        if lock_pending_key_rotation(conn, rotation.id)?.is_none() {
+            return Ok(Pending::Running);
+        }
+        let current_address = wallet_address(vault, account_data, None)?;
+        if current_address == rotation.new_address {
+            return Ok(Pending::Resume);
+        }
+        if rotation.created_at > abandoned_before {
+            return Ok(Pending::Running);
+        }
+        if current_address != rotation.old_address {
+            anyhow::bail!("Key rotation {} matches neither key of account {}", rotation.id, account_data.id);
+        }
+        delete_key_rotation(conn, rotation.id);
+        Ok(Pending::Dropped)
+    })?;
+    match pending {
+        Pending::Resume => Ok(Some(rotation)),
+        Pending::Running => Ok(None),
+        Pending::Dropped => {
+            // Only once the row is gone, as `PgVault` keeps the key on it.
+            vault.delete(KeySlot::Rotation(rotation.id))?;
+            Ok(None)
+        }
+    }
+}
+
+// Points the account and its sub-accounts at the addresses of the key stored
+// by `rotate_wallet`.
+fn apply_rotation(connection: &mut PgConnection, vault: &dyn KeyVault, account_data: &Account, format: WalletFormat, rotation: &KeyRotation) -> anyhow::Result<Account> {
+    let slot = KeySlot::Account(account_data.id);
+    let sub_addresses = list_sub_accounts(connection, account_data.id)
+        .into_iter()
+        .map(|sub| {
+            let account_id = vault.derive(slot, format.scheme, Some(&sub.derivation_path))?;
+            Ok((sub.id, format_ss58(&account_id, format.ss58_prefix)))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let linked_evm_address = vault.evm_address(slot)?;
+
+    apply_key_rotation(connection, rotation, linked_evm_address.as_deref(), &sub_addresses)
+}
+
+fn reveal_cooldown_active(connection: &mut PgConnection, account_id: i64) -> bool {
+    let cooldown = Duration::seconds(env_seconds("MNEMONIC_REVEAL_COOLDOWN", DEFAULT_REVEAL_COOLDOWN));
+    match last_mnemonic_reveal(connection, account_id) {
+        Some(revealed_at) => revealed_at + cooldown > Utc::now().naive_utc(),
+        None => false
+    }
+}
+
+pub async fn index() -> impl Responder {
+    HttpResponse::Ok().body("Welcome to the face-recognization rust server!")
+}
+
+pub async fn status() -> impl Responder {
+    HttpResponse::Ok().body("Status: Running")
+}
+
+// Signs in with a face match. A bearer token is not accepted here: it would
+// let a stolen access token be swapped for a new login of its own.
+pub async fn get_wallet_post(req: HttpRequest, info: web::Json<GetWalletInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address)) // Ensure address is referenced correctly
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().content_type("application/json").json(response_message);
+    }
+
+    let account_data = &results[0];
+
+    if !info.feature.as_ref().is_some_and(|probe| face_matches(connection, account_data, probe)) {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Authentication failed".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    match issue_token(info.address.clone(), info.uid, &[SCOPE_WALLET]) {
+        Ok(issued) => {
+            let (jtoken, refresh) = match open_session(connection, &req, account_data.id, issued, None) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `open_session`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+            let response_message = LinkedWalletResponse {
+                wallet: WalletResponse {
+                    result: "Success".to_string(),
+                    msg: "Got wallet successfully".to_string(),
+                    wallet_address: info.address.clone(),
+                    token: jtoken,
+                    refresh_token: refresh,
+                },
+                evm_wallet_address: checksummed_evm_address(account_data)
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn create_wallet_post(req: HttpRequest, vault: web::Data<dyn KeyVault>, info: web::Json<CreateWalletInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let (duplicate_policy, conflict) = match duplicate_enrollment(connection, &info.feature, None) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Invalid face feature".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    let conflict_account_id = conflict.filter(|_| is_admin(&req));
+    if duplicate_policy == DuplicatePolicy::Reject && conflict.is_some() {
+        let response_message = CreateWalletResponse {
+            wallet: WalletResponse {
+                result: "Error".to_string(),
+                msg: "Face is already enrolled".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            },
+            evm_wallet_address: "".to_string(),
+            conflict_account_id,
+            backup_shares: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let mnem: SecretString;
+    let language = match parse_language(info.language.as_deref().unwrap_or("english")) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Unsupported mnemonic language".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().content_type("application/json").json(response_message);
+        }
+    };
+    let format = match WalletFormat::from_env().and_then(|f| f.with(info.key_scheme.as_deref(), info.ss58_prefix)) {
+        Ok(t) => t,
+        Err(e) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: e.to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().content_type("application/json").json(response_message);
+        }
+    };
+    match generate_mnemonic(info.word_count.unwrap_or(DEFAULT_MNEMONIC_WORD_COUNT), language) {
+        Ok(t) => mnem = t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `generate_mnemonic`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().content_type("application/json").json(response_message);
+        }
+    };
+    let backup_shares = match &info.backup {
+        Some(backup) => match backup_shares(&mnem, backup) {
+            Ok(t) => t,
+            Err(e) => {
+                let response_message = WalletResponse {
+                    result: "Error".to_string(),
+                    msg: format!("Invalid backup: {}", e),
+                    wallet_address: "".to_string(),
+                    token: "".to_string(),
+                    refresh_token: "".to_string(),
+                };
+                return HttpResponse::Ok().content_type("application/json").json(response_message);
+            }
+        },
+        None => vec![]
+    };
+    let pair: WalletPair;
+    match get_wallet_pair(mnem.expose_secret(), None, format.scheme) {
+        Ok(t) => pair = t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `get_wallet_pair`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().content_type("application/json").json(response_message);
+        }
+    };
+    let address_to_fund = ss58_address(&pair, format.ss58_prefix);
+    let linked_evm_address = match get_evm_address(mnem.expose_secret()) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `get_evm_address`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().content_type("application/json").json(response_message);
+        }
+    };
+    match issue_token(address_to_fund.clone(), info.uid, &[SCOPE_WALLET]) {
+        Ok(issued) => {
+            let (template, seed) = match enrollment_template(&info.feature) {
+                Ok(t) => t,
+                Err(_) => {
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Invalid face feature".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+            let created = create_account(connection, &NewAccountData {
+                uid: info.uid,
+                address: Some(&address_to_fund),
+                evm_address: Some(&linked_evm_address),
+                token: Some(&issued.token),
+                feature: Some(&template),
+                template_seed: seed.as_deref(),
+                key_scheme: format.scheme.as_str(),
+                ss58_prefix: format.ss58_prefix as i32,
+                secret_kind: SECRET_KIND_MNEMONIC,
+            })
+                .and_then(|created| store_wallet_key(connection, vault.as_ref(), created, &mnem));
+            let myaccount = match created {
+                Ok(t) => t,
+                // The address, EVM address and public key are unique, as for
+                // an imported wallet.
+                Err(e) if is_unique_violation(&e) => {
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Wallet is already enrolled".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                },
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to create account: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `create_account`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+
+            if let (DuplicatePolicy::Flag, Some(conflict_id)) = (duplicate_policy, conflict) {
+                flag_duplicate_enrollment(connection, myaccount.id, conflict_id);
+            }
+            let (jtoken, refresh) = match open_session(connection, &req, myaccount.id, issued, None) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `open_session`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+            let response_message = CreateWalletResponse {
+                wallet: WalletResponse {
+                    result: "Success".to_string(),
+                    msg: "Created wallet successfully".to_string(),
+                    wallet_address: address_to_fund,
+                    token: jtoken,
+                    refresh_token: refresh,
+                },
+                evm_wallet_address: checksummed_evm_address(&myaccount),
+                conflict_account_id,
+                backup_shares
+            };
+
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+        
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+
+pub async fn recover_wallet_post(req: HttpRequest, info: web::Json<RecoverWalletInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.recover_key)) // Ensure address is referenced correctly
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().content_type("application/json").json(response_message);
+    }
+
+    let account_data = &results[0];
+    let accepted = match match_account(connection, account_data, &info.feature) {
+        Ok(Some((t, _))) => t,
+        Ok(None) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "No face template enrolled for the account".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Invalid face feature".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    if !accepted {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Face verification failed".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    // Whoever lost the device may still hold its tokens; sign them all out
+    // before handing the recovered wallet a fresh session.
+    revoke_account_sessions(connection, account_data.id, Utc::now().naive_utc());
+
+    // The token is bound to the enrolled owner, not to the uid in the request.
+    match issue_token(info.recover_key.clone(), account_data.uid, &[SCOPE_WALLET]) {
+        Ok(issued) => {
+            let (jtoken, refresh) = match open_session(connection, &req, account_data.id, issued, None) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `open_session`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+            let response_message = LinkedWalletResponse {
+                wallet: WalletResponse {
+                    result: "Success".to_string(),
+                    msg: "Got wallet successfully".to_string(),
+                    wallet_address: account_data.address.clone().unwrap_or_default(),
+                    token: jtoken,
+                    refresh_token: refresh,
+                },
+                evm_wallet_address: checksummed_evm_address(account_data)
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+// Finds the wallet of a face alone, so it is open to admins only: anyone else
+// could look up the uid and address behind any face.
+pub async fn identify_post(req: HttpRequest, info: web::Json<IdentifyInfo>) -> impl Responder {
+    if !is_admin(&req) {
+        let response_message = IdentifyResponse {
+            result: "Error".to_string(),
+            msg: "Admin key required".to_string(),
+            matched: false,
+            wallet_address: "".to_string(),
+            uid: 0,
+            score: None,
+            threshold: 0.0,
+            metric: "".to_string()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let config = match MatchConfig::from_env() {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = IdentifyResponse {
+                result: "Error".to_string(),
+                msg: "Invalid face match configuration".to_string(),
+                matched: false,
+                wallet_address: "".to_string(),
+                uid: 0,
+                score: None,
+                threshold: 0.0,
+                metric: "".to_string()
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let connection = &mut establish_connection();
+
+    let closest = match closest_account(connection, &info.feature, &config, None) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = IdentifyResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `closest_account`".to_string(),
+                matched: false,
+                wallet_address: "".to_string(),
+                uid: 0,
+                score: None,
+                threshold: config.threshold,
+                metric: config.metric.as_str().to_string()
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    match closest {
+        Some((row, score)) if config.metric.accepts(score, config.threshold) => {
+            let response_message = IdentifyResponse {
+                result: "Success".to_string(),
+                msg: "Identified wallet successfully".to_string(),
+                matched: true,
+                wallet_address: row.address.clone().unwrap_or_default(),
+                uid: row.uid,
+                score: Some(score),
+                threshold: config.threshold,
+                metric: config.metric.as_str().to_string()
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        _ => {
+            let response_message = IdentifyResponse {
+                result: "Error".to_string(),
+                msg: "No matching face found".to_string(),
+                matched: false,
+                wallet_address: "".to_string(),
+                uid: 0,
+                score: None,
+                threshold: config.threshold,
+                metric: config.metric.as_str().to_string()
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn reveal_ticket_post(info: web::Json<RevealTicketInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = RevealTicketResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            ticket: "".to_string(),
+            expires_in: 0
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !face_matches(connection, account_data, &info.feature) {
+        let response_message = RevealTicketResponse {
+            result: "Error".to_string(),
+            msg: "Face verification failed".to_string(),
+            ticket: "".to_string(),
+            expires_in: 0
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    if account_data.secret_kind != SECRET_KIND_MNEMONIC {
+        let response_message = RevealTicketResponse {
+            result: "Error".to_string(),
+            msg: "Wallet has no mnemonic, export it as a keystore instead".to_string(),
+            ticket: "".to_string(),
+            expires_in: 0
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    if reveal_cooldown_active(connection, account_data.id) {
+        let response_message = RevealTicketResponse {
+            result: "Error".to_string(),
+            msg: "Mnemonic was revealed recently, please try again later".to_string(),
+            ticket: "".to_string(),
+            expires_in: 0
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let ttl = env_seconds("MNEMONIC_REVEAL_TICKET_TTL", DEFAULT_REVEAL_TICKET_TTL);
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(ttl);
+    let issued = create_reveal_ticket(connection, account_data.id, &generate_code(48), expires_at);
+
+    let response_message = RevealTicketResponse {
+        result: "Success".to_string(),
+        msg: "Issued reveal ticket successfully".to_string(),
+        ticket: issued.ticket,
+        expires_in: ttl
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+pub async fn reveal_mnemonic_post(req: HttpRequest, vault: web::Data<dyn KeyVault>, info: web::Json<RevealMnemonicInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+    let origin = request_origin(&req);
+
+    let ticket_data = match find_reveal_ticket(connection, &info.ticket) {
+        Some(t) => t,
+        None => {
+            let response_message = RevealMnemonicResponse {
+                result: "Error".to_string(),
+                msg: "Invalid or expired reveal ticket".to_string(),
+                mnemonic: SecretString::default()
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    let audit = |connection: &mut PgConnection, outcome: &str| {
+        create_reveal_audit(connection, ticket_data.account_id, Some(ticket_data.id), outcome, origin.ip.as_deref(), origin.user_agent.as_deref())
+    };
+
+    // Keystore imports hold a raw secret key, which must not pass for a mnemonic.
+    if find_account(connection, ticket_data.account_id).is_some_and(|t| t.secret_kind != SECRET_KIND_MNEMONIC) {
+        let response_message = RevealMnemonicResponse {
+            result: "Error".to_string(),
+            msg: "Wallet has no mnemonic, export it as a keystore instead".to_string(),
+            mnemonic: SecretString::default()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    if reveal_cooldown_active(connection, ticket_data.account_id) {
+        if let Err(e) = audit(connection, REVEAL_OUTCOME_COOLDOWN) {
+            error!(target: LOG_TARGET, "Failed to audit mnemonic reveal: {:?}", e);
+        }
+        let response_message = RevealMnemonicResponse {
+            result: "Error".to_string(),
+            msg: "Mnemonic was revealed recently, please try again later".to_string(),
+            mnemonic: SecretString::default()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    // The ticket is consumed together with its audit entry, and stays unused
+    // when the key cannot be revealed.
+    let revealed = connection.transaction::<_, anyhow::Error, _>(|conn| {
+        if redeem_reveal_ticket(conn, &info.ticket, Utc::now().naive_utc())?.is_none() {
+            return Ok(None);
+        }
+        audit(conn, REVEAL_OUTCOME_REVEALED)?;
+        Ok(Some(vault.reveal(KeySlot::Account(ticket_data.account_id))?))
+    });
+    match revealed {
+        Ok(Some(phrase)) => {
+            let response_message = RevealMnemonicResponse {
+                result: "Success".to_string(),
+                msg: "Revealed mnemonic successfully".to_string(),
+                mnemonic: phrase
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Ok(None) => {
+            if let Err(e) = audit(connection, REVEAL_OUTCOME_INVALID_TICKET) {
+                error!(target: LOG_TARGET, "Failed to audit mnemonic reveal: {:?}", e);
+            }
+            let response_message = RevealMnemonicResponse {
+                result: "Error".to_string(),
+                msg: "Invalid or expired reveal ticket".to_string(),
+                mnemonic: SecretString::default()
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = RevealMnemonicResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `reveal`".to_string(),
+                mnemonic: SecretString::default()
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn reissue_template_post(info: web::Json<ReissueTemplateInfo>) -> impl Responder {
+    match ProtectionConfig::from_env() {
+        Ok(protection) if protection.mode != ProtectionMode::None => {},
+        _ => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Template protection is disabled".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    }
+
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    // The fresh capture must still match the template being cancelled.
+    let account_data = &results[0];
+    if !face_matches(connection, account_data, &info.feature) {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Face verification failed".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let reissued = enrollment_template(&info.feature).and_then(|(template, seed)| {
+        update_template(connection, account_data.id, &template, seed.as_deref())
+    });
+    match reissued {
+        Ok(_) => {
+            // Tokens issued against the cancelled template are signed out too.
+            revoke_account_sessions(connection, account_data.id, Utc::now().naive_utc());
+            let response_message = WalletResponse {
+                result: "Success".to_string(),
+                msg: "Re-issued face template successfully".to_string(),
+                wallet_address: account_data.address.clone().unwrap_or_default(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `update_template`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn auth_challenge_post(info: web::Json<AuthChallengeInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = AuthChallengeResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            challenge: "".to_string(),
+            expires_in: 0
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let ttl = env_seconds("AUTH_CHALLENGE_TTL", DEFAULT_AUTH_CHALLENGE_TTL);
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(ttl);
+    let issued = create_auth_challenge(connection, results[0].id, &generate_code(48), expires_at);
+
+    // The wallet signs `<Bytes>{challenge}</Bytes>`, as polkadot-js `signRaw` does.
+    let response_message = AuthChallengeResponse {
+        result: "Success".to_string(),
+        msg: "Issued auth challenge successfully".to_string(),
+        challenge: issued.nonce,
+        expires_in: ttl
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+pub async fn auth_verify_post(req: HttpRequest, info: web::Json<AuthVerifyInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if redeem_auth_challenge(connection, account_data.id, &info.challenge, Utc::now().naive_utc()).is_none() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Invalid or expired challenge".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let verified = wallet_format(account_data).and_then(|format| {
+        verify_signed_polkadot_msg(&info.signature, &info.challenge, &info.address, format)
+    });
+    if !matches!(verified, Ok(true)) {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Signature verification failed".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    match issue_token(info.address.clone(), account_data.uid, &[SCOPE_WALLET]) {
+        Ok(issued) => {
+            let (jtoken, refresh) = match open_session(connection, &req, account_data.id, issued, None) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `open_session`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+            let response_message = WalletResponse {
+                result: "Success".to_string(),
+                msg: "Signed in successfully".to_string(),
+                wallet_address: info.address.clone(),
+                token: jtoken,
+                refresh_token: refresh,
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn siwe_nonce_get() -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let ttl = env_seconds("SIWE_NONCE_TTL", DEFAULT_SIWE_NONCE_TTL);
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(ttl);
+    let issued = create_siwe_nonce(connection, &generate_code(32), expires_at);
+
+    let response_message = SiweNonceResponse {
+        result: "Success".to_string(),
+        msg: "Issued SIWE nonce successfully".to_string(),
+        nonce: issued.nonce,
+        expires_in: ttl
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+pub async fn siwe_verify_post(req: HttpRequest, info: web::Json<SiweVerifyInfo>) -> impl Responder {
+    let checked = SiweConfig::from_env().map_err(|e| e.to_string()).and_then(|config| {
+        let message = SiweMessage::parse(&info.message).map_err(|e| e.to_string())?;
+        message.validate(&config, Utc::now()).map_err(|e| e.to_string())?;
+        match verify_signed_evm_msg(&info.signature, &info.message, &message.address) {
+            Ok(true) => Ok(message),
+            Ok(false) => Err("Signature verification failed".to_string()),
+            Err(e) => Err(e.to_string())
+        }
+    });
+    let message = match checked {
+        Ok(t) => t,
+        Err(e) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: e,
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let connection = &mut establish_connection();
+
+    if redeem_siwe_nonce(connection, &message.nonce, Utc::now().naive_utc()).is_none() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Invalid or expired nonce".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let signer = message.address.to_lowercase();
+    let results = account
+        .filter(evm_address.eq(&signer))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    match issue_token(signer, results[0].uid, &[SCOPE_WALLET]) {
+        Ok(issued) => {
+            let (jtoken, refresh) = match open_session(connection, &req, results[0].id, issued, None) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `open_session`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
+            let response_message = WalletResponse {
+                result: "Success".to_string(),
+                msg: "Signed in successfully".to_string(),
+                wallet_address: message.address,
+                token: jtoken,
+                refresh_token: refresh,
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+// Derives a new sub-account of the wallet at `derivation_path` and records it under `name`.
+pub async fn create_sub_account_post(wallet: OptionalWallet, vault: web::Data<dyn KeyVault>, info: web::Json<SubAccountInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !wallet_authorized(connection, wallet.0.as_ref(), account_data, info.feature.as_ref()) {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Authentication failed".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    if info.name.is_empty() || info.name.chars().count() > MAX_SUB_ACCOUNT_NAME_LEN {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Sub-account name must be 1 to 64 characters".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let derived_address = match wallet_address(vault.as_ref(), account_data, Some(&info.derivation_path)) {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = SubAccountResponse {
+                result: "Error".to_string(),
+                msg: "Invalid derivation path".to_string(),
+                sub_accounts: vec![]
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    match create_sub_account(connection, account_data.id, &info.name, &info.derivation_path, &derived_address) {
+        Ok(sub) => {
+            let response_message = SubAccountResponse {
+                result: "Success".to_string(),
+                msg: "Created sub-account successfully".to_string(),
+                sub_accounts: vec![sub.into()]
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(e) if is_unique_violation(&e) => {
+            let response_message = SubAccountResponse {
+                result: "Error".to_string(),
+                msg: "A sub-account with this name or derivation path already exists".to_string(),
+                sub_accounts: vec![]
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(e) => {
+            error!(target: LOG_TARGET, "Failed to create sub-account: {:?}", e);
+            let response_message = SubAccountResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `create_sub_account`".to_string(),
+                sub_accounts: vec![]
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+// Renames the sub-account of the wallet at `derivation_path`.
+pub async fn rename_sub_account_post(wallet: OptionalWallet, info: web::Json<SubAccountInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !wallet_authorized(connection, wallet.0.as_ref(), account_data, info.feature.as_ref()) {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Authentication failed".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    if info.name.is_empty() || info.name.chars().count() > MAX_SUB_ACCOUNT_NAME_LEN {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Sub-account name must be 1 to 64 characters".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    match rename_sub_account(connection, account_data.id, &info.derivation_path, &info.name) {
+        Ok(Some(sub)) => {
+            let response_message = SubAccountResponse {
+                result: "Success".to_string(),
+                msg: "Renamed sub-account successfully".to_string(),
+                sub_accounts: vec![sub.into()]
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Ok(None) => {
+            let response_message = SubAccountResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the sub-account".to_string(),
+                sub_accounts: vec![]
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(e) if is_unique_violation(&e) => {
+            let response_message = SubAccountResponse {
+                result: "Error".to_string(),
+                msg: "A sub-account with this name already exists".to_string(),
+                sub_accounts: vec![]
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(e) => {
+            error!(target: LOG_TARGET, "Failed to rename sub-account: {:?}", e);
+            let response_message = SubAccountResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `rename_sub_account`".to_string(),
+                sub_accounts: vec![]
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn list_sub_accounts_post(wallet: OptionalWallet, info: web::Json<GetWalletInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !wallet_authorized(connection, wallet.0.as_ref(), account_data, info.feature.as_ref()) {
+        let response_message = SubAccountResponse {
+            result: "Error".to_string(),
+            msg: "Authentication failed".to_string(),
+            sub_accounts: vec![]
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let response_message = SubAccountResponse {
+        result: "Success".to_string(),
+        msg: "Listed sub-accounts successfully".to_string(),
+        sub_accounts: list_sub_accounts(connection, account_data.id).into_iter().map(SubAccountEntry::from).collect()
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Exports the wallet as a polkadot-js compatible keystore; like revealing the
+// mnemonic this needs a fresh face match.
+pub async fn export_keystore_post(vault: web::Data<dyn KeyVault>, info: web::Json<ExportKeystoreInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = KeystoreResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            keystore: None
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !face_matches(connection, account_data, &info.feature) {
+        let response_message = KeystoreResponse {
+            result: "Error".to_string(),
+            msg: "Face verification failed".to_string(),
+            keystore: None
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    if info.password.expose_secret().len() < MIN_PASSWORD_LEN {
+        let response_message = KeystoreResponse {
+            result: "Error".to_string(),
+            msg: format!("Keystore password must be at least {} characters", MIN_PASSWORD_LEN),
+            keystore: None
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let sub = match &info.sub_account {
+        Some(name) => match find_sub_account(connection, account_data.id, name) {
+            Some(t) => Some(t),
+            None => {
+                let response_message = KeystoreResponse {
+                    result: "Error".to_string(),
+                    msg: "Can not find the sub-account".to_string(),
+                    keystore: None
+                };
+                return HttpResponse::Ok().json(response_message);
+            }
+        },
+        None => None
+    };
+    let exported = wallet_format(account_data).and_then(|format| {
+        if format.scheme != KeyScheme::Sr25519 {
+            return Ok(None);
+        }
+        let keystore = vault.export_keystore(
+            KeySlot::Account(account_data.id),
+            format.scheme,
+            sub.as_ref().map(|t| t.derivation_path.as_str()),
+            sub.as_ref().map_or(info.address.as_str(), |t| t.address.as_str()),
+            info.password.expose_secret(),
+            info.name.as_deref().unwrap_or_default()
+        )?;
+        Ok(Some(keystore))
+    });
+    match exported {
+        Ok(Some(t)) => {
+            let response_message = KeystoreResponse {
+                result: "Success".to_string(),
+                msg: "Exported keystore successfully".to_string(),
+                keystore: Some(t)
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Ok(None) => {
+            let response_message = KeystoreResponse {
+                result: "Error".to_string(),
+                msg: "Only sr25519 wallets can be exported as a keystore".to_string(),
+                keystore: None
+            };
+            HttpResponse::Ok().json(response_message)
+        },
+        Err(e) => {
+            error!(target: LOG_TARGET, "Failed to export keystore: {:?}", e);
+            let response_message = KeystoreResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `export_keystore`".to_string(),
+                keystore: None
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn import_keystore_post(req: HttpRequest, vault: web::Data<dyn KeyVault>, info: web::Json<ImportKeystoreInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+    import_wallet(connection, vault.as_ref(), &req, &info.into_inner().into())
+}
+
+// Issues a challenge for the key behind `address`, in any SS58 format.
+pub async fn import_challenge_get(info: web::Query<ImportChallengeInfo>) -> impl Responder {
+    if parse_ss58(&info.address).is_err() {
+        let response_message = AuthChallengeResponse {
+            result: "Error".to_string(),
+            msg: "Invalid SS58 address".to_string(),
+            challenge: "".to_string(),
+            expires_in: 0
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let connection = &mut establish_connection();
+
+    let ttl = env_seconds("IMPORT_CHALLENGE_TTL", DEFAULT_IMPORT_CHALLENGE_TTL);
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(ttl);
+    let issued = create_import_challenge(connection, &generate_code(48), &info.address, expires_at);
+
+    // The imported key signs `<Bytes>{challenge}</Bytes>`, as for `/auth/verify`.
+    let response_message = AuthChallengeResponse {
+        result: "Success".to_string(),
+        msg: "Issued import challenge successfully".to_string(),
+        challenge: issued.nonce,
+        expires_in: ttl
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Resolves the secret of an import request, checking the ownership proof that
+// has to accompany a keystore.
+fn import_secret(connection: &mut PgConnection, info: &ImportWalletInfo, format: WalletFormat) -> Result<(SecretString, &'static str), String> {
+    if let Some(phrase) = &info.mnemonic {
+        if !is_mnemonic(phrase.expose_secret()) {
+            return Err("Invalid mnemonic".to_string());
+        }
+        get_wallet_pair(phrase.expose_secret(), None, format.scheme).map_err(|_| "Invalid mnemonic".to_string())?;
+        return Ok((phrase.clone(), SECRET_KIND_MNEMONIC));
+    }
+    if format.scheme != KeyScheme::Sr25519 {
+        return Err("Keystores hold sr25519 keys only".to_string());
+    }
+
+    let (Some(keystore), Some(password), Some(challenge), Some(signature)) =
+        (&info.keystore, &info.password, &info.challenge, &info.signature) else {
+        return Err("A mnemonic, or a keystore with its password and a signed challenge, is required".to_string());
+    };
+    let pair = decrypt_keystore(keystore, password.expose_secret()).map_err(|e| e.to_string())?;
+    let wallet_pair = WalletPair::Sr25519(pair.clone());
+    let signer = ss58_address(&wallet_pair, format.ss58_prefix);
+
+    // The challenge must have been issued for this very key, and is only
+    // consumed once its signature checks out.
+    let now = Utc::now().naive_utc();
+    let bound = find_import_challenge(connection, challenge, now)
+        .and_then(|t| parse_ss58(&t.address).ok())
+        .is_some_and(|(challenge_account, _)| challenge_account == wallet_pair.account_id());
+    if !bound {
+        return Err("Invalid or expired challenge".to_string());
+    }
+    if !matches!(verify_signed_polkadot_msg(signature, challenge, &signer, format), Ok(true)) {
+        return Err("Signature verification failed".to_string());
+    }
+    if redeem_import_challenge(connection, challenge, now).is_none() {
+        return Err("Invalid or expired challenge".to_string());
+    }
+    Ok((secret_seed(&pair), SECRET_KIND_SEED))
+}
+
+pub async fn import_wallet_post(req: HttpRequest, vault: web::Data<dyn KeyVault>, info: web::Json<ImportWalletInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+    import_wallet(connection, vault.as_ref(), &req, &info)
+}
+
+// Enrolls the wallet of an import request under its face, once the secret
+// and its ownership proof check out.
+fn import_wallet(connection: &mut PgConnection, vault: &dyn KeyVault, req: &HttpRequest, info: &ImportWalletInfo) -> HttpResponse {
+    let imported = WalletFormat::from_env()
+        .and_then(|f| f.with(info.key_scheme.as_deref(), info.ss58_prefix))
+        .map_err(|e| e.to_string())
+        .and_then(|format| Ok((import_secret(connection, info, format)?, format)));
+    match imported {
+        Ok(((secret, kind), format)) => enroll_existing_wallet(connection, vault, req, ExistingWallet {
+            user_id: info.uid,
+            probe: &info.feature,
+            secret: &secret,
+            kind,
+            format
+        }),
+        Err(e) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: e,
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            HttpResponse::Ok().json(response_message)
+        }
+    }
+}
+
+pub async fn sign_post(req: HttpRequest, wallet: OptionalWallet, vault: web::Data<dyn KeyVault>, info: web::Json<SignInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = SignResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            signature: "".to_string(),
+            signer: "".to_string(),
+            payload_hash: "".to_string()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let policy = match SigningPolicy::from_env() {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = SignResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `SigningPolicy`".to_string(),
+                signature: "".to_string(),
+                signer: "".to_string(),
+                payload_hash: "".to_string()
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    let scope = normalize_scope(info.scope.as_deref().unwrap_or(DEFAULT_SIGN_SCOPE));
+    if scope.is_empty() || scope.len() > MAX_SIGN_SCOPE_LEN {
+        let response_message = SignResponse {
+            result: "Error".to_string(),
+            msg: "Scope must be 1 to 64 characters".to_string(),
+            signature: "".to_string(),
+            signer: "".to_string(),
+            payload_hash: "".to_string()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+    if policy.is_sensitive(&scope) && info.feature.is_none() {
+        let response_message = SignResponse {
+            result: "Error".to_string(),
+            msg: format!("Scope `{}` requires face verification", scope),
+            signature: "".to_string(),
+            signer: "".to_string(),
+            payload_hash: "".to_string()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !wallet_authorized(connection, wallet.0.as_ref(), account_data, info.feature.as_ref()) {
+        let response_message = SignResponse {
+            result: "Error".to_string(),
+            msg: "Authentication failed".to_string(),
+            signature: "".to_string(),
+            signer: "".to_string(),
+            payload_hash: "".to_string()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let payload = match info.encoding.as_deref().unwrap_or("utf8") {
+        "utf8" => Ok(info.payload.as_bytes().to_vec()),
+        "hex" => hex::decode(info.payload.strip_prefix("0x").unwrap_or(&info.payload))
+            .map_err(|_| "Payload is not valid hex".to_string()),
+        other => Err(format!("Unsupported payload encoding: {}", other))
+    };
+    let payload = match payload {
+        Ok(t) if t.len() > policy.max_payload_len => Err(format!("Payload must be at most {} bytes", policy.max_payload_len)),
+        other => other
+    };
+    let payload = match payload {
+        Ok(t) => wrap_bytes(&t),
+        Err(e) => {
+            let response_message = SignResponse {
+                result: "Error".to_string(),
+                msg: e,
+                signature: "".to_string(),
+                signer: "".to_string(),
+                payload_hash: "".to_string()
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let sub = match &info.sub_account {
+        Some(name) => match find_sub_account(connection, account_data.id, name) {
+            Some(t) => Some(t),
+            None => {
+                let response_message = SignResponse {
+                    result: "Error".to_string(),
+                    msg: "Can not find the sub-account".to_string(),
+                    signature: "".to_string(),
+                    signer: "".to_string(),
+                    payload_hash: "".to_string()
+                };
+                return HttpResponse::Ok().json(response_message);
+            }
+        },
+        None => None
+    };
+    let signed = wallet_format(account_data).and_then(|format| {
+        let derivation_path = sub.as_ref().map(|t| t.derivation_path.as_str());
+        vault.sign(KeySlot::Account(account_data.id), format.scheme, derivation_path, &payload)
+    });
+    let signed = match signed {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = SignResponse {
+                result: "Error".to_string(),
+                msg: "Internal error on `sign`".to_string(),
+                signature: "".to_string(),
+                signer: "".to_string(),
+                payload_hash: "".to_string()
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    // A signature that cannot be audited is not handed out.
+    let origin = request_origin(&req);
+    let hashed = hex::encode(blake2_256(&payload));
+    let audited = create_signature_audit(connection, &NewSignatureAudit {
+        account_id: account_data.id,
+        sub_account_id: sub.as_ref().map(|t| t.id),
+        scope: &scope,
+        payload_hash: &hashed,
+        payload_len: payload.len() as i32,
+        face_verified: info.feature.is_some(),
+        ip: origin.ip.as_deref(),
+        user_agent: origin.user_agent.as_deref(),
+    });
+    if let Err(e) = audited {
+        error!(target: LOG_TARGET, "Failed to audit signature: {:?}", e);
+        let response_message = SignResponse {
+            result: "Error".to_string(),
+            msg: "Internal error on `create_signature_audit`".to_string(),
+            signature: "".to_string(),
+            signer: "".to_string(),
+            payload_hash: "".to_string()
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
 
-#[derive(Serialize, Debug)]
-pub struct WalletResponse {
-    result: String,
-    msg: String,
-    wallet_address: String,
-    mnemonic: String,
-    token: String,
-    feature: Vec<u8>
+    let response_message = SignResponse {
+        result: "Success".to_string(),
+        msg: "Signed payload successfully".to_string(),
+        signature: format!("0x{}", hex::encode(signed)),
+        signer: sub.map(|t| t.address).unwrap_or_else(|| info.address.clone()),
+        payload_hash: format!("0x{}", hashed)
+    };
+    HttpResponse::Ok().json(response_message)
 }
 
-pub async fn index() -> impl Responder {
-    HttpResponse::Ok().body("Welcome to the face-recognization rust server!")
+// Reassembles a wallet from its backup shares and binds it to the given face:
+// the stored template is replaced when the wallet is still enrolled, and the
+// wallet is enrolled anew otherwise.
+// Whether `phrase` is the mnemonic of the wallet on the row, derived with the
+// scheme and SS58 prefix the wallet was created and backed up with.
+fn derives_wallet(phrase: &SecretString, account_data: &Account) -> bool {
+    wallet_format(account_data)
+        .and_then(|format| Ok(ss58_address(&get_wallet_pair(phrase.expose_secret(), None, format.scheme)?, format.ss58_prefix)))
+        .is_ok_and(|derived| account_data.address.as_deref() == Some(derived.as_str()))
 }
 
-pub async fn status() -> impl Responder {
-    HttpResponse::Ok().body("Status: Running")
-}
+pub async fn recover_from_shares_post(req: HttpRequest, info: web::Json<RecoverSharesInfo>) -> impl Responder {
+    let recovered = info
+        .shares
+        .iter()
+        .map(|share| Share::parse(share.expose_secret()))
+        .collect::<anyhow::Result<Vec<Share>>>()
+        .and_then(|shares| combine_shares(&shares))
+        .and_then(SecretString::try_from)
+        .and_then(|secret| {
+            if !is_mnemonic(secret.expose_secret()) {
+                anyhow::bail!("Shares do not reconstruct a mnemonic");
+            }
+            Ok(secret)
+        });
+    let secret = match recovered {
+        Ok(t) => t,
+        Err(_) => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Shares do not reconstruct a valid wallet".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
 
-pub async fn get_wallet_post(info: web::Json<GetWalletInfo>) -> impl Responder {
     let connection = &mut establish_connection();
-
     let results = account
-        .filter(address.eq(&info.address)) // Ensure address is referenced correctly
-        .limit(1)
+        .filter(uid.eq(info.uid))
         .load::<Account>(connection)
         .expect("Error loading account");
 
-    if results.is_empty() {
-        let response_message = WalletResponse {
-            result: "Error".to_string(),
-            msg: "Can not find the account".to_string(),
-            wallet_address: "".to_string(),
-            mnemonic: "".to_string(),
-            token: "".to_string(),
-            feature: Vec::new()
-        };
-        return HttpResponse::Ok().content_type("application/json").json(response_message);
-    }
-
-    let account_data = &results[0];
-    match generate_token(info.address.clone(), info.uid) {
-        Ok(jtoken) => {
+    // Shares from before a key rotation derive the swept old key, which no
+    // account has any more. Wallets missing from the database are enrolled
+    // through `/import_wallet` instead.
+    let account_data = match results.iter().find(|row| derives_wallet(&secret, row)) {
+        Some(t) => t,
+        None => {
             let response_message = WalletResponse {
-                result: "Success".to_string(),
-                msg: "Got wallet successfully".to_string(),
-                wallet_address: info.address.clone(),
-                mnemonic: account_data.mnemonic.clone().unwrap_or_default(),
-                token: jtoken,
-                feature: account_data.feature.clone().unwrap_or_else(Vec::new),
+                result: "Error".to_string(),
+                msg: "Shares do not belong to an enrolled wallet of this user".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
             };
-            HttpResponse::Ok().json(response_message)
-        },
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    // The new face goes through the same duplicate check as an enrollment,
+    // leaving out the template it replaces.
+    let (duplicate_policy, conflict) = match duplicate_enrollment(connection, &info.feature, Some(account_data.id)) {
+        Ok(t) => t,
         Err(_) => {
             let response_message = WalletResponse {
                 result: "Error".to_string(),
-                msg: "Internal error on `generate_token`".to_string(),
+                msg: "Invalid face feature".to_string(),
                 wallet_address: "".to_string(),
-                mnemonic: "".to_string(),
                 token: "".to_string(),
-                feature: Vec::new()
+                refresh_token: "".to_string(),
             };
-            HttpResponse::Ok().json(response_message)
+            return HttpResponse::Ok().json(response_message);
         }
+    };
+    if duplicate_policy == DuplicatePolicy::Reject && conflict.is_some() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Face is already enrolled".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
     }
-}
 
-pub async fn create_wallet_post(info: web::Json<CreateWalletInfo>) -> impl Responder {
-    let mnem: Option<String>;
-    println!("======================  create wallet 1 ");
-    match generate_mnemonic() {
-        Ok(t) => mnem = Some(t),
+    let rebound = enrollment_template(&info.feature)
+        .and_then(|(template, seed)| update_template(connection, account_data.id, &template, seed.as_deref()));
+    if let Err(e) = rebound {
+        error!(target: LOG_TARGET, "Failed to replace the face template: {:?}", e);
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Internal error on `update_template`".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+    if let (DuplicatePolicy::Flag, Some(conflict_id)) = (duplicate_policy, conflict) {
+        flag_duplicate_enrollment(connection, account_data.id, conflict_id);
+    }
+    // Whoever held the wallet before recovery is signed out.
+    revoke_account_sessions(connection, account_data.id, Utc::now().naive_utc());
+
+    // The wallet keeps the address it was enrolled with.
+    let recovered_address = account_data.address.clone().unwrap_or_default();
+    let issued = match issue_token(recovered_address.clone(), info.uid, &[SCOPE_WALLET]) {
+        Ok(t) => t,
         Err(_) => {
             let response_message = WalletResponse {
                 result: "Error".to_string(),
-                msg: "Internal error on `generate_mnemonic`".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
                 wallet_address: "".to_string(),
-                mnemonic: "".to_string(),
                 token: "".to_string(),
-                feature: Vec::new()
+                refresh_token: "".to_string(),
             };
-            return HttpResponse::Ok().content_type("application/json").json(response_message);
+            return HttpResponse::Ok().json(response_message);
         }
     };
-    println!("======================  create wallet 2 ");
-    let pair: Pair;
-    match get_pair(&mnem.clone().unwrap(), None) {
-        Ok(t) => pair = t,
-        Err(_) => {
+    let (jtoken, refresh) = match open_session(connection, &req, account_data.id, issued, None) {
+        Ok(t) => t,
+        Err(e) => {
+            error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
             let response_message = WalletResponse {
                 result: "Error".to_string(),
-                msg: "Internal error on `get_pair`".to_string(),
+                msg: "Internal error on `open_session`".to_string(),
                 wallet_address: "".to_string(),
-                mnemonic: "".to_string(),
                 token: "".to_string(),
-                feature: Vec::new()
+                refresh_token: "".to_string(),
             };
-            return HttpResponse::Ok().content_type("application/json").json(response_message);
+            return HttpResponse::Ok().json(response_message);
         }
     };
-    println!("======================  create wallet 3 ");
-    let address_to_fund: String;
-    match get_pair_address_as_ss58_address(pair) {
-        Ok(t) => address_to_fund = t,
+    let response_message = WalletResponse {
+        result: "Success".to_string(),
+        msg: "Recovered wallet successfully".to_string(),
+        wallet_address: recovered_address,
+        token: jtoken,
+        refresh_token: refresh,
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Replaces a possibly compromised mnemonic and sweeps the old balance to the
+// new address. A rotation whose sweep failed is retried rather than rotated
+// again; gateway authorization and buckets are carried over where possible.
+pub async fn rotate_key_post(req: HttpRequest, vault: web::Data<dyn KeyVault>, info: web::Json<RotateKeyInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let results = account
+        .filter(address.eq(&info.address))
+        .filter(uid.eq(info.uid))
+        .limit(1)
+        .load::<Account>(connection)
+        .expect("Error loading account");
+
+    if results.is_empty() {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Can not find the account".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let account_data = &results[0];
+    if !face_matches(connection, account_data, &info.feature) {
+        let response_message = WalletResponse {
+            result: "Error".to_string(),
+            msg: "Face verification failed".to_string(),
+            wallet_address: "".to_string(),
+            token: "".to_string(),
+            refresh_token: "".to_string(),
+        };
+        return HttpResponse::Ok().json(response_message);
+    }
+
+    let format = match wallet_format(account_data) {
+        Ok(t) => t,
         Err(_) => {
             let response_message = WalletResponse {
                 result: "Error".to_string(),
-                msg: "Internal error on `get_pair_address_as_ss58_address`".to_string(),
+                msg: "Internal error on `wallet_format`".to_string(),
                 wallet_address: "".to_string(),
-                mnemonic: "".to_string(),
                 token: "".to_string(),
-                feature: Vec::new()
+                refresh_token: "".to_string(),
             };
-            return HttpResponse::Ok().content_type("application/json").json(response_message);
+            return HttpResponse::Ok().json(response_message);
         }
-    }
-    println!("======================  create wallet 4 ");
-    match generate_token(address_to_fund.clone(), info.uid.clone()) {
-        Ok(jtoken) => {
-            let connection = &mut establish_connection();
+    };
 
-            let myaccount = create_account(connection, info.uid.clone(), Some(&mnem.unwrap()), Some(&address_to_fund.clone()), Some(&jtoken.clone()), Some(&info.feature));
-            
-            println!("test account: {:?}", myaccount.clone());
+    let rotation = match pending_rotation(connection, vault.as_ref(), account_data) {
+        Ok(Some(t)) => Ok(t),
+        Ok(None) => rotate_wallet(connection, vault.as_ref(), account_data, format, &info),
+        Err(e) => Err(e)
+    };
+    let rotated = rotation.and_then(|rotation| {
+        let rotated = apply_rotation(connection, vault.as_ref(), account_data, format, &rotation)?;
+        Ok((rotation, rotated))
+    });
+    let (rotation, rotated) = match rotated {
+        Ok(t) => t,
+        Err(e) => {
+            let msg = if is_unique_violation(&e) {
+                "Key rotation already in progress"
+            } else {
+                error!(target: LOG_TARGET, "Failed to rotate the key of account {}: {:?}", account_data.id, e);
+                "Internal error on `rotate_key`"
+            };
             let response_message = WalletResponse {
-                result: "Success".to_string(),
-                msg: "Created wallet successfully".to_string(),
-                wallet_address: address_to_fund,
-                mnemonic: "".to_string(),
-                token: jtoken,
-                feature: Vec::new()
+                result: "Error".to_string(),
+                msg: msg.to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
             };
-        
-            println!("test response_message: {:?}", response_message);
-            HttpResponse::Ok().json(response_message)
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    // Sessions opened with the replaced key are not carried over.
+    revoke_account_sessions(connection, account_data.id, Utc::now().naive_utc());
+
+    // The old key is deleted only once the wallet and every sub-account have
+    // been swept and hold nothing it would still be needed for.
+    let old_slot = KeySlot::Rotation(rotation.id);
+    let mut targets = vec![(None, rotation.new_address.clone())];
+    targets.extend(
+        list_sub_accounts(connection, rotated.id)
+            .into_iter()
+            .map(|sub| (Some(sub.derivation_path), sub.address))
+    );
+    let mut outcomes = vec![];
+    for (derivation_path, new_address) in &targets {
+        let outcome = sweep_balance(vault.as_ref(), old_slot, format.scheme, derivation_path.as_deref(), new_address).await;
+        if let Err(e) = &outcome {
+            error!(target: LOG_TARGET, "Failed to sweep key rotation {} to {}: {:?}", rotation.id, new_address, e);
+        }
+        outcomes.push(outcome);
+    }
+    let swept = match sweep_progress(&outcomes) {
+        SweepProgress::Done { transferred } => match vault.delete(old_slot) {
+            Ok(()) => Ok(transferred),
+            Err(e) => {
+                error!(target: LOG_TARGET, "Failed to delete the key of rotation {}: {:?}", rotation.id, e);
+                Err("Rotated the key, but sweeping the old balance failed, rotate again to retry")
+            }
         },
-        Err(_) => {
+        SweepProgress::Held => Err("Rotated the key, but the old address still holds reserved or frozen funds, rotate again once they are released"),
+        SweepProgress::Failed => Err("Rotated the key, but sweeping the old balance failed, rotate again to retry")
+    };
+    let swept = match swept {
+        Ok(t) => t,
+        Err(msg) => {
             let response_message = WalletResponse {
                 result: "Error".to_string(),
-                msg: "Internal error on `generate_token`".to_string(),
-                wallet_address: "".to_string(),
-                mnemonic: "".to_string(),
+                msg: msg.to_string(),
+                wallet_address: rotation.new_address.clone(),
                 token: "".to_string(),
-                feature: Vec::new()
+                refresh_token: "".to_string(),
             };
-        
-            HttpResponse::Ok().json(response_message)
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+    complete_key_rotation(connection, rotation.id, Utc::now().naive_utc());
+
+    let new_slot = KeySlot::Account(account_data.id);
+    let gateway_authorized = authorize_account_to_upload_to_gateway(vault.as_ref(), new_slot).await.is_ok();
+    // Buckets are re-created empty under the new account. Stored files stay
+    // owned by the old address, which the response names so they can be found.
+    let mut migrated_buckets = vec![];
+    for name in user_buckets(&rotation.old_address).await.unwrap_or_default() {
+        let signed = match vault.sign(new_slot, format.scheme, None, &wrap_bytes(name.as_bytes())) {
+            Ok(t) => t,
+            Err(_) => continue
+        };
+        if let Ok(true) = create_bucket(&name, &format!("0x{}", hex::encode(signed)), &rotation.new_address).await {
+            migrated_buckets.push(name);
         }
     }
-}
 
+    let (jtoken, refresh) = match issue_token(rotation.new_address.clone(), info.uid, &[SCOPE_WALLET]) {
+        Ok(issued) => open_session(connection, &req, rotated.id, issued, None).unwrap_or_else(|e| {
+            error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+            ("".to_string(), "".to_string())
+        }),
+        Err(_) => ("".to_string(), "".to_string())
+    };
+    let response_message = RotateKeyResponse {
+        wallet: WalletResponse {
+            result: "Success".to_string(),
+            msg: "Rotated wallet key successfully".to_string(),
+            wallet_address: rotation.new_address,
+            token: jtoken,
+            refresh_token: refresh,
+        },
+        evm_wallet_address: checksummed_evm_address(&rotated),
+        old_address: rotation.old_address,
+        swept,
+        gateway_authorized,
+        migrated_buckets
+    };
+    HttpResponse::Ok().json(response_message)
+}
 
-pub async fn recover_wallet_post(info: web::Json<RecoverWalletInfo>) -> impl Responder {
+// Describes the wallet behind the bearer token of a request.
+pub async fn me_get(wallet: AuthenticatedWallet) -> impl Responder {
     let connection = &mut establish_connection();
 
     let results = account
-        .filter(address.eq(&info.recover_key)) // Ensure address is referenced correctly
+        .filter(address.eq(&wallet.address))
+        .filter(uid.eq(wallet.uid))
         .limit(1)
         .load::<Account>(connection)
         .expect("Error loading account");
 
     if results.is_empty() {
-        let response_message = WalletResponse {
+        let response_message = WalletSessionResponse {
             result: "Error".to_string(),
             msg: "Can not find the account".to_string(),
+            uid: wallet.uid,
             wallet_address: "".to_string(),
-            mnemonic: "".to_string(),
-            token: "".to_string(),
-            feature: Vec::new()
+            evm_wallet_address: "".to_string(),
+            scopes: vec![]
         };
-        return HttpResponse::Ok().content_type("application/json").json(response_message);
+        return HttpResponse::Ok().json(response_message);
     }
 
-    let account_data = &results[0];
-    match generate_token(info.recover_key.clone(), info.uid) {
-        Ok(jtoken) => {
+    let response_message = WalletSessionResponse {
+        result: "Success".to_string(),
+        msg: "Token is valid".to_string(),
+        uid: wallet.uid,
+        wallet_address: wallet.address.clone(),
+        evm_wallet_address: checksummed_evm_address(&results[0]),
+        scopes: wallet.scopes
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Exchanges a refresh token for a new access token and refresh token. Each
+// refresh token is accepted once; presenting one that was already exchanged
+// revokes its whole family, so a stolen token dies with the legitimate one.
+pub async fn refresh_token_post(req: HttpRequest, info: web::Json<RefreshTokenInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+    let now = Utc::now().naive_utc();
+    let token_hash = hash_refresh_token(info.refresh_token.expose_secret());
+
+    let redeemed = match redeem_refresh_token(connection, &token_hash, now) {
+        Redemption::Redeemed(t) => t,
+        rejected => {
+            let msg = match rejected {
+                Redemption::Rejected(replayed, rejection) if rejection.revokes_family() => {
+                    revoke_session_family(connection, &replayed.family_id, now);
+                    "Refresh token reuse detected, sign in again"
+                },
+                Redemption::Rejected(_, _) => "Refresh token expired or revoked",
+                _ => "Invalid refresh token"
+            };
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: msg.to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let account_data = match find_account(connection, redeemed.account_id) {
+        Some(t) => t,
+        None => {
+            let response_message = WalletResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the account".to_string(),
+                wallet_address: "".to_string(),
+                token: "".to_string(),
+                refresh_token: "".to_string(),
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let refreshed_address = account_data.address.clone().unwrap_or_default();
+    match issue_token(refreshed_address.clone(), account_data.uid, &[SCOPE_WALLET]) {
+        Ok(issued) => {
+            let (jtoken, refresh) = match open_session(connection, &req, account_data.id, issued, Some(&redeemed.family_id)) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to open session: {:?}", e);
+                    let response_message = WalletResponse {
+                        result: "Error".to_string(),
+                        msg: "Internal error on `open_session`".to_string(),
+                        wallet_address: "".to_string(),
+                        token: "".to_string(),
+                        refresh_token: "".to_string(),
+                    };
+                    return HttpResponse::Ok().json(response_message);
+                }
+            };
             let response_message = WalletResponse {
                 result: "Success".to_string(),
-                msg: "Got wallet successfully".to_string(),
-                wallet_address: account_data.address.clone().unwrap_or_default(),
-                mnemonic: "".to_string(),
+                msg: "Refreshed token successfully".to_string(),
+                wallet_address: refreshed_address,
                 token: jtoken,
-                feature: account_data.feature.clone().unwrap_or_else(Vec::new),
+                refresh_token: refresh,
             };
             HttpResponse::Ok().json(response_message)
         },
         Err(_) => {
             let response_message = WalletResponse {
                 result: "Error".to_string(),
-                msg: "Internal error on `generate_token`".to_string(),
+                msg: "Internal error on `issue_token`".to_string(),
                 wallet_address: "".to_string(),
                 token: "".to_string(),
-                mnemonic: "".to_string(),
-                feature: Vec::new()
+                refresh_token: "".to_string(),
             };
             HttpResponse::Ok().json(response_message)
         }
     }
-}
\ No newline at end of file
+}
+
+// Signs out the session of the requesting token, together with the refresh
+// token it was issued with.
+pub async fn logout_post(wallet: AuthenticatedWallet) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let current = match find_session_by_jti(connection, &wallet.jti) {
+        Some(t) => t,
+        None => {
+            let response_message = SessionResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the session".to_string(),
+                sessions: vec![]
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    revoke_session_family(connection, &current.family_id, Utc::now().naive_utc());
+    let response_message = SessionResponse {
+        result: "Success".to_string(),
+        msg: "Signed out successfully".to_string(),
+        sessions: vec![]
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Lists the signed-in sessions of the wallet behind the requesting token.
+pub async fn sessions_get(wallet: AuthenticatedWallet) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let current = match find_session_by_jti(connection, &wallet.jti) {
+        Some(t) => t,
+        None => {
+            let response_message = SessionResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the session".to_string(),
+                sessions: vec![]
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let sessions = list_active_sessions(connection, current.account_id, Utc::now().naive_utc())
+        .into_iter()
+        .map(|listed| session_entry(listed, &current))
+        .collect();
+    let response_message = SessionResponse {
+        result: "Success".to_string(),
+        msg: "Listed sessions successfully".to_string(),
+        sessions
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Signs out one session of the wallet, e.g. a lost device.
+pub async fn revoke_session_post(wallet: AuthenticatedWallet, info: web::Json<RevokeSessionInfo>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let current = match find_session_by_jti(connection, &wallet.jti) {
+        Some(t) => t,
+        None => {
+            let response_message = SessionResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the session".to_string(),
+                sessions: vec![]
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    let target = match find_account_session(connection, current.account_id, info.session_id) {
+        Some(t) => t,
+        None => {
+            let response_message = SessionResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the session".to_string(),
+                sessions: vec![]
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    revoke_session_family(connection, &target.family_id, Utc::now().naive_utc());
+    let response_message = SessionResponse {
+        result: "Success".to_string(),
+        msg: "Revoked session successfully".to_string(),
+        sessions: vec![]
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// Signs out every session of the wallet, including the requesting one.
+pub async fn revoke_all_sessions_post(wallet: AuthenticatedWallet) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    let current = match find_session_by_jti(connection, &wallet.jti) {
+        Some(t) => t,
+        None => {
+            let response_message = SessionResponse {
+                result: "Error".to_string(),
+                msg: "Can not find the session".to_string(),
+                sessions: vec![]
+            };
+            return HttpResponse::Ok().json(response_message);
+        }
+    };
+
+    revoke_account_sessions(connection, current.account_id, Utc::now().naive_utc());
+    let response_message = SessionResponse {
+        result: "Success".to_string(),
+        msg: "Revoked all sessions successfully".to_string(),
+        sessions: vec![]
+    };
+    HttpResponse::Ok().json(response_message)
+}
+
+// These run against the database at `DATABASE_URL`, migrated with
+// `diesel migration run`: `cargo test -- --ignored`.
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jwt::revocation::is_revoked;
+    use crate::schema::session;
+    use cess_rust_sdk::subxt::ext::sp_core::crypto::AccountId32;
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::{body::MessageBody, test, App};
+    use serde_json::{json, Value};
+
+    struct Login {
+        session: Session,
+        access_token: String,
+        refresh_token: String,
+    }
+
+    fn enroll_wallet(connection: &mut PgConnection) -> Account {
+        let wallet_address = format_ss58(&AccountId32::new(rand::random()), 42);
+        create_account(connection, &NewAccountData {
+            uid: rand::random::<u32>() as i64,
+            address: Some(&wallet_address),
+            evm_address: None,
+            token: None,
+            feature: None,
+            template_seed: None,
+            key_scheme: KeyScheme::Sr25519.as_str(),
+            ss58_prefix: 42,
+            secret_kind: SECRET_KIND_MNEMONIC,
+        })
+        .unwrap()
+    }
+
+    fn sign_in_from(connection: &mut PgConnection, req: &HttpRequest, wallet: &Account) -> Login {
+        let issued = issue_token(wallet.address.clone().unwrap_or_default(), wallet.uid, &[SCOPE_WALLET]).unwrap();
+        let jti = issued.jti.clone();
+        let (access_token, refresh_token) = open_session(connection, req, wallet.id, issued, None).unwrap();
+        Login {
+            session: find_session_by_jti(connection, &jti).unwrap(),
+            access_token,
+            refresh_token,
+        }
+    }
+
+    fn sign_in(connection: &mut PgConnection, wallet: &Account) -> Login {
+        sign_in_from(connection, &test::TestRequest::default().to_http_request(), wallet)
+    }
+
+    fn bearer(req: test::TestRequest, login: &Login) -> test::TestRequest {
+        req.insert_header((header::AUTHORIZATION, format!("Bearer {}", login.access_token)))
+    }
+
+    fn me_request(login: &Login) -> test::TestRequest {
+        bearer(test::TestRequest::get().uri("/me"), login)
+    }
+
+    // Status of a request, including those the bearer guard refuses.
+    async fn call_status<S, R, B>(app: &S, req: R) -> StatusCode
+    where
+        S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    {
+        match test::try_call_service(app, req).await {
+            Ok(res) => res.status(),
+            Err(e) => e.error_response().status(),
+        }
+    }
+
+    async fn call_json<S, R, B>(app: &S, req: R) -> Value
+    where
+        S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        test::read_body_json(test::try_call_service(app, req).await.unwrap()).await
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database at DATABASE_URL"]
+    async fn test_logout() {
+        let connection = &mut establish_connection();
+        let app = test::init_service(App::new().configure(crate::routes::configure)).await;
+        let wallet = enroll_wallet(connection);
+        let phone = sign_in(connection, &wallet);
+        let laptop = sign_in(connection, &wallet);
+
+        let signed_out = call_json(&app, bearer(test::TestRequest::post().uri("/logout"), &phone).to_request()).await;
+        assert_eq!(signed_out["result"], "Success");
+        assert_eq!(call_status(&app, me_request(&phone).to_request()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call_status(&app, me_request(&laptop).to_request()).await, StatusCode::OK);
+
+        // The refresh token of the login is revoked with it.
+        let refreshed = call_json(&app, test::TestRequest::post()
+            .uri("/token/refresh")
+            .set_json(json!({"refresh_token": phone.refresh_token}))
+            .to_request()).await;
+        assert_eq!(refreshed["msg"], "Refresh token expired or revoked");
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database at DATABASE_URL"]
+    async fn test_revoke_session() {
+        let connection = &mut establish_connection();
+        let app = test::init_service(App::new().configure(crate::routes::configure)).await;
+        let wallet = enroll_wallet(connection);
+        let other_wallet = enroll_wallet(connection);
+        let phone = sign_in(connection, &wallet);
+        let lost = sign_in(connection, &wallet);
+        let stranger = sign_in(connection, &other_wallet);
+
+        // A session of another account is not found, and stays signed in.
+        let revoked = call_json(&app, bearer(test::TestRequest::post()
+            .uri("/sessions/revoke")
+            .set_json(json!({"session_id": stranger.session.id})), &phone).to_request()).await;
+        assert_eq!(revoked["result"], "Error");
+        assert_eq!(revoked["msg"], "Can not find the session");
+        assert_eq!(call_status(&app, me_request(&stranger).to_request()).await, StatusCode::OK);
+
+        let revoked = call_json(&app, bearer(test::TestRequest::post()
+            .uri("/sessions/revoke")
+            .set_json(json!({"session_id": lost.session.id})), &phone).to_request()).await;
+        assert_eq!(revoked["result"], "Success");
+        assert_eq!(call_status(&app, me_request(&lost).to_request()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call_status(&app, me_request(&phone).to_request()).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database at DATABASE_URL"]
+    async fn test_revoke_all_sessions() {
+        let connection = &mut establish_connection();
+        let app = test::init_service(App::new().configure(crate::routes::configure)).await;
+        let wallet = enroll_wallet(connection);
+        let other_wallet = enroll_wallet(connection);
+        let phone = sign_in(connection, &wallet);
+        let laptop = sign_in(connection, &wallet);
+        let stranger = sign_in(connection, &other_wallet);
+
+        let revoked = call_json(&app, bearer(test::TestRequest::post().uri("/sessions/revoke_all"), &phone).to_request()).await;
+        assert_eq!(revoked["result"], "Success");
+        assert_eq!(call_status(&app, me_request(&phone).to_request()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call_status(&app, me_request(&laptop).to_request()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call_status(&app, me_request(&stranger).to_request()).await, StatusCode::OK);
+        assert!(list_active_sessions(connection, wallet.id, Utc::now().naive_utc()).is_empty());
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database at DATABASE_URL"]
+    async fn test_list_active_sessions() {
+        let connection = &mut establish_connection();
+        let app = test::init_service(App::new().configure(crate::routes::configure)).await;
+        let wallet = enroll_wallet(connection);
+        let now = Utc::now().naive_utc();
+        let phone = sign_in(connection, &wallet);
+        let signed_out = sign_in(connection, &wallet);
+        revoke_session_family(connection, &signed_out.session.family_id, now);
+
+        // Access tokens expired an hour ago; one login can still be refreshed.
+        let expired_at = now - Duration::hours(1);
+        let idle = create_session(connection, wallet.id, &generate_code(32), "idle-family", None, None, expired_at).unwrap();
+        create_refresh_token(connection, wallet.id, "idle-family", &hash_refresh_token(&generate_code(64)), now + Duration::days(1));
+        create_session(connection, wallet.id, &generate_code(32), "gone-family", None, None, expired_at).unwrap();
+
+        // Refreshing keeps the login, listed once with its latest access token.
+        let refreshed = call_json(&app, test::TestRequest::post()
+            .uri("/token/refresh")
+            .set_json(json!({"refresh_token": phone.refresh_token}))
+            .to_request()).await;
+        assert_eq!(refreshed["result"], "Success");
+        let phone_jti = list_active_sessions(connection, wallet.id, now)
+            .into_iter()
+            .find(|listed| listed.family_id == phone.session.family_id)
+            .unwrap()
+            .jti;
+        assert_ne!(phone_jti, phone.session.jti);
+
+        let listed: Vec<i64> = list_active_sessions(connection, wallet.id, now)
+            .into_iter()
+            .map(|listed| listed.id)
+            .collect();
+        assert_eq!(listed.len(), 2);
+        assert!(listed.contains(&idle.id));
+        assert!(!listed.contains(&phone.session.id));
+        assert!(!listed.contains(&signed_out.session.id));
+
+        let refreshed_login = Login {
+            session: find_session_by_jti(connection, &phone_jti).unwrap(),
+            access_token: refreshed["token"].as_str().unwrap().to_string(),
+            refresh_token: refreshed["refresh_token"].as_str().unwrap().to_string(),
+        };
+        let sessions = call_json(&app, bearer(test::TestRequest::get().uri("/sessions"), &refreshed_login).to_request()).await;
+        let current: Vec<bool> = sessions["sessions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["current"].as_bool().unwrap())
+            .collect();
+        assert_eq!(current, vec![true, false]);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database at DATABASE_URL"]
+    async fn test_sync_revoked_sessions() {
+        let connection = &mut establish_connection();
+        let wallet = enroll_wallet(connection);
+        let now = Utc::now().naive_utc();
+        let revoked_elsewhere = sign_in(connection, &wallet);
+        let active = sign_in(connection, &wallet);
+
+        // Revoked by another server instance, so only the table knows.
+        diesel::update(session::table.find(revoked_elsewhere.session.id))
+            .set(session::revoked_at.eq(Some(now)))
+            .execute(connection)
+            .unwrap();
+        assert!(!is_revoked(&revoked_elsewhere.session.jti));
+
+        assert!(sync_revoked_sessions(connection, now) >= 1);
+        assert!(is_revoked(&revoked_elsewhere.session.jti));
+        assert!(!is_revoked(&active.session.jti));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database at DATABASE_URL"]
+    async fn test_session_origin() {
+        let connection = &mut establish_connection();
+        let wallet = enroll_wallet(connection);
+        let user_agent = "Mozilla/5.0 ".repeat(30);
+        let req = test::TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.9"))
+            .insert_header((header::USER_AGENT, user_agent.as_str()))
+            .to_http_request();
+
+        let login = sign_in_from(connection, &req, &wallet);
+        assert_eq!(login.session.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(login.session.device.unwrap().chars().count(), MAX_AUDIT_USER_AGENT_LEN);
+    }
+}
@@ -0,0 +1,183 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, bail, Result};
+use dotenvy::dotenv;
+use std::collections::HashMap;
+use std::env;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::secret::SecretBytes;
+
+const NONCE_LEN: usize = 12;
+
+/// Versioned master keys loaded from `<PREFIX>_MASTER_KEYS` (`version:hex,...`)
+/// and `<PREFIX>_MASTER_KEY_VERSION`, the version used for new data.
+pub struct Keyring {
+    keys: HashMap<i32, [u8; 32]>,
+    current: i32,
+}
+
+impl Keyring {
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        dotenv().ok();
+
+        let keys_var = format!("{}_MASTER_KEYS", prefix);
+        let version_var = format!("{}_MASTER_KEY_VERSION", prefix);
+        let keys_str = env::var(&keys_var).map_err(|_| anyhow!("{} must be set", keys_var))?;
+        let current = env::var(&version_var)
+            .map_err(|_| anyhow!("{} must be set", version_var))?
+            .parse::<i32>()?;
+
+        Keyring::parse(&keys_str, current)
+    }
+
+    pub fn parse(keys_str: &str, current: i32) -> Result<Self> {
+        let mut keys = HashMap::new();
+        for entry in keys_str.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (version, key_hex) = match entry.split_once(':') {
+                Some(t) => t,
+                None => bail!("Master key entry must be `version:hex`"),
+            };
+            let key_bytes = Zeroizing::new(hex::decode(key_hex)?);
+            let key: [u8; 32] = key_bytes
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!("Master key {} must be 32 bytes", version))?;
+            keys.insert(version.parse::<i32>()?, key);
+        }
+        if !keys.contains_key(&current) {
+            bail!("Master key version {} is not configured", current);
+        }
+        Ok(Keyring { keys, current })
+    }
+
+    pub fn current_version(&self) -> i32 {
+        self.current
+    }
+
+    fn key(&self, version: i32) -> Result<&[u8; 32]> {
+        self.keys
+            .get(&version)
+            .ok_or_else(|| anyhow!("Master key version {} is not configured", version))
+    }
+}
+
+impl Drop for Keyring {
+    fn drop(&mut self) {
+        self.keys.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+/// A value encrypted under a random per-row data key, with the data key itself
+/// wrapped by the keyring's master key of `key_version`. The ciphertext is bound
+/// to associated data naming the row it belongs to, so an envelope copied onto
+/// another row does not open.
+pub struct Envelope {
+    pub ciphertext: Vec<u8>,
+    pub wrapped_key: Vec<u8>,
+    pub key_version: i32,
+}
+
+// AES-256-GCM with a random nonce, stored as `nonce || ciphertext || tag`.
+// `aad` is authenticated but not stored; decryption needs the same bytes.
+pub(crate) fn encrypt(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+pub(crate) fn decrypt(key: &[u8; 32], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        bail!("Ciphertext is too short");
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| anyhow!("Decryption failed"))
+}
+
+// Only the ciphertext is bound to `aad`: a data key is useless without it, and
+// leaving the wrapped key unbound lets `rewrap` work without knowing the row.
+pub fn seal(keyring: &Keyring, plaintext: &[u8], aad: &[u8]) -> Result<Envelope> {
+    let data_key: Zeroizing<[u8; 32]> = Zeroizing::new(Aes256Gcm::generate_key(&mut OsRng).into());
+    let ciphertext = encrypt(&data_key, plaintext, aad)?;
+    let wrapped_key = encrypt(keyring.key(keyring.current)?, data_key.as_slice(), &[])?;
+
+    Ok(Envelope {
+        ciphertext,
+        wrapped_key,
+        key_version: keyring.current,
+    })
+}
+
+pub fn open(keyring: &Keyring, envelope: &Envelope, aad: &[u8]) -> Result<SecretBytes> {
+    let data_key = Zeroizing::new(decrypt(keyring.key(envelope.key_version)?, &envelope.wrapped_key, &[])?);
+    let data_key: &[u8; 32] = data_key
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow!("Invalid data key length"))?;
+    Ok(SecretBytes::from(decrypt(data_key, &envelope.ciphertext, aad)?))
+}
+
+/// Re-wraps the data key under the current master key; the ciphertext is untouched.
+pub fn rewrap(keyring: &Keyring, envelope: &Envelope) -> Result<Envelope> {
+    let data_key = Zeroizing::new(decrypt(keyring.key(envelope.key_version)?, &envelope.wrapped_key, &[])?);
+
+    Ok(Envelope {
+        ciphertext: envelope.ciphertext.clone(),
+        wrapped_key: encrypt(keyring.key(keyring.current)?, &data_key, &[])?,
+        key_version: keyring.current,
+    })
+}
+
+/// Encrypts directly under the current master key, for values small enough
+/// not to need a data key. Returns the ciphertext and the key version used.
+pub fn encrypt_with_master(keyring: &Keyring, plaintext: &[u8]) -> Result<(Vec<u8>, i32)> {
+    Ok((encrypt(keyring.key(keyring.current)?, plaintext, &[])?, keyring.current))
+}
+
+pub fn decrypt_with_master(keyring: &Keyring, key_version: i32, sealed: &[u8]) -> Result<Vec<u8>> {
+    decrypt(keyring.key(key_version)?, sealed, &[])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY_1: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_2: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+    #[test]
+    fn test_seal_open_rewrap() {
+        let old = Keyring::parse(&format!("1:{}", KEY_1), 1).unwrap();
+        let envelope = seal(&old, b"chicken sport cereal", b"account/1").unwrap();
+        assert_eq!(envelope.key_version, 1);
+        assert_eq!(open(&old, &envelope, b"account/1").unwrap().expose_secret(), b"chicken sport cereal");
+        // An envelope moved to another row does not open there.
+        assert!(open(&old, &envelope, b"account/2").is_err());
+
+        let rotated = Keyring::parse(&format!("1:{},2:{}", KEY_1, KEY_2), 2).unwrap();
+        let rewrapped = rewrap(&rotated, &envelope).unwrap();
+        assert_eq!(rewrapped.key_version, 2);
+        assert_eq!(open(&rotated, &rewrapped, b"account/1").unwrap().expose_secret(), b"chicken sport cereal");
+
+        let only_new = Keyring::parse(&format!("2:{}", KEY_2), 2).unwrap();
+        assert!(open(&only_new, &envelope, b"account/1").is_err());
+    }
+
+    #[test]
+    fn test_master_key_encryption() {
+        let keyring = Keyring::parse(&format!("1:{},2:{}", KEY_1, KEY_2), 2).unwrap();
+        let (sealed, version) = encrypt_with_master(&keyring, &[1, 2, 3]).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(decrypt_with_master(&keyring, 2, &sealed).unwrap(), vec![1, 2, 3]);
+        assert!(decrypt_with_master(&keyring, 1, &sealed).is_err());
+    }
+}
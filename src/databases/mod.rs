@@ -1,13 +1,73 @@
+use anyhow::{anyhow, Result};
+use cess_rust_sdk::subxt::ext::sp_core::crypto::{AccountId32, Ss58Codec};
+use chrono::NaiveDateTime;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use dotenvy::dotenv;
+use std::collections::HashSet;
 use std::env;
 
 // Assuming `models` and `schema` are modules defined at the same level as this file.
 pub mod models;
 
-use crate::databases::models::{NewAccount, Account};  // Correcting the path if necessary
-use crate::schema::account;  // This might need to be corrected based on your project structure
+use crate::crypto::{
+    decrypt_with_master, encrypt_with_master, open, rewrap, seal, Envelope, Keyring,
+};
+use crate::databases::models::{
+    NewAccount, NewAccountData, Account, AuthChallenge, ImportChallenge, KeyRotation, NewAuthChallenge,
+    NewImportChallenge, NewKeyRotation, NewMnemonicRevealAudit, NewRefreshToken, NewRevealTicket, NewSession, NewSignatureAudit, NewSiweNonce, NewSubAccount,
+    RefreshToken, RevealTicket, Session, SiweNonce, SubAccount,
+};  // Correcting the path if necessary
+use crate::face::template::FaceTemplate;
+use crate::jwt::revocation::revoke_tokens;
+use crate::secret::SecretString;
+use crate::schema::{
+    account, auth_challenge, import_challenge, key_rotation, mnemonic_reveal_audit,
+    refresh_token, reveal_ticket, session, signature_audit, siwe_nonce, sub_account,
+};  // This might need to be corrected based on your project structure
+
+// Environment prefix of the master keys wrapping per-row mnemonic data keys.
+pub const MNEMONIC_KEY_PREFIX: &str = "MNEMONIC";
+// Environment prefix of the master keys encrypting face templates; kept apart
+// from the mnemonic keys so that one leaking does not expose the other.
+pub const FEATURE_KEY_PREFIX: &str = "FEATURE";
+
+// Kinds of wallet secret recorded in `account.secret_kind`: a BIP39 mnemonic, or
+// the `0x` hex secret key of a wallet imported without one.
+pub const SECRET_KIND_MNEMONIC: &str = "mnemonic";
+pub const SECRET_KIND_SEED: &str = "seed";
+
+// Outcomes recorded in `mnemonic_reveal_audit`.
+pub const REVEAL_OUTCOME_REVEALED: &str = "revealed";
+pub const REVEAL_OUTCOME_COOLDOWN: &str = "cooldown";
+pub const REVEAL_OUTCOME_INVALID_TICKET: &str = "invalid_ticket";
+
+// Associated data binding a sealed mnemonic to the row it is stored on.
+fn account_key_aad(account_id: i64) -> Vec<u8> {
+    format!("account/{}", account_id).into_bytes()
+}
+
+fn rotation_key_aad(rotation_id: i64) -> Vec<u8> {
+    format!("key_rotation/{}", rotation_id).into_bytes()
+}
+
+// Whether `e` is a database error for a row that violates a unique constraint,
+// such as a second account with an already enrolled address.
+pub fn is_unique_violation(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<diesel::result::Error>(),
+        Some(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _))
+    )
+}
+
+// The account id an SS58 address of any prefix encodes, as stored in
+// `account.public_key`.
+fn address_public_key(address: &str) -> Result<Vec<u8>> {
+    match AccountId32::from_ss58check_with_version(address) {
+        Ok((account_id, _)) => Ok(<[u8; 32]>::from(account_id).to_vec()),
+        Err(_) => Err(anyhow!("Invalid SS58 address: {}", address)),
+    }
+}
 
 // Function to establish a connection to the PostgreSQL database.
 pub fn establish_connection() -> PgConnection {
@@ -19,24 +79,1015 @@ pub fn establish_connection() -> PgConnection {
 }
 
 // Function to create a new account in the database.
-pub fn create_account(
-    conn: &mut PgConnection, 
-    uid: i64, 
-    mnemonic: Option<&str>, 
-    address: Option<&str>, 
-    token: Option<&str>, 
-    feature: Option<&[u8]>) -> Account {
+// The face template and its projection seed are encrypted under the feature
+// master key before they are written; the template's model id is kept in the
+// clear so matching can filter on it. The wallet key is stored separately, through the key vault.
+pub fn create_account(conn: &mut PgConnection, data: &NewAccountData) -> Result<Account> {
+    let keyring = feature_keyring()?;
+    let sealed_feature = match data.feature {
+        Some(template) => Some(encrypt_with_master(&keyring, &template.encode())?),
+        None => None
+    };
+    let sealed_seed = match data.template_seed {
+        Some(seed) => Some(encrypt_with_master(&keyring, seed)?),
+        None => None
+    };
+
+    let account_public_key = data.address.map(address_public_key).transpose()?;
 
     let new_account = NewAccount { 
-        uid, 
-        mnemonic, 
-        address, 
-        token,
-        feature  // Passing the binary data for the feature
+        uid: data.uid, 
+        address: data.address, 
+        evm_address: data.evm_address,
+        token: data.token,
+        feature: sealed_feature.as_ref().map(|(sealed, _)| sealed.as_slice()),  // Passing the binary data for the feature
+        mnemonic_ciphertext: None,
+        mnemonic_data_key: None,
+        mnemonic_key_version: None,
+        feature_key_version: sealed_feature.as_ref().map(|(_, version)| *version),
+        template_seed: sealed_seed.as_ref().map(|(sealed, _)| sealed.as_slice()),
+        feature_model: data.feature.map(|template| template.model_id.as_str()),
+        key_scheme: data.key_scheme,
+        ss58_prefix: data.ss58_prefix,
+        template_seed_key_version: sealed_seed.as_ref().map(|(_, version)| *version),
+        secret_kind: data.secret_kind,
+        public_key: account_public_key.as_deref()
     };
 
-    diesel::insert_into(account::table)
+    Ok(diesel::insert_into(account::table)
         .values(&new_account)
+        .get_result(conn)?)
+}
+
+// Function to get an account by its id.
+pub fn find_account(conn: &mut PgConnection, account_id: i64) -> Option<Account> {
+    account::table
+        .find(account_id)
+        .first(conn)
+        .optional()
+        .expect("Error loading account")
+}
+
+// Function to remove an account whose key could not be stored.
+pub fn delete_account(conn: &mut PgConnection, account_id: i64) -> usize {
+    diesel::delete(account::table.find(account_id))
+        .execute(conn)
+        .expect("Error deleting account")
+}
+
+// Function to seal a mnemonic under a fresh data key and store it on the
+// account row, replacing any previous one.
+pub fn store_account_mnemonic(conn: &mut PgConnection, account_id: i64, phrase: &SecretString) -> Result<()> {
+    let envelope = seal(&Keyring::from_env(MNEMONIC_KEY_PREFIX)?, phrase.expose_secret().as_bytes(), &account_key_aad(account_id))?;
+    let updated = diesel::update(account::table.find(account_id))
+        .set((
+            account::mnemonic.eq(None::<String>),
+            account::mnemonic_ciphertext.eq(Some(envelope.ciphertext)),
+            account::mnemonic_data_key.eq(Some(envelope.wrapped_key)),
+            account::mnemonic_key_version.eq(Some(envelope.key_version)),
+        ))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(anyhow!("Account {} does not exist", account_id));
+    }
+    Ok(())
+}
+
+// Function to drop the mnemonic of an account, in plaintext or sealed.
+pub fn clear_account_mnemonic(conn: &mut PgConnection, account_id: i64) -> Result<()> {
+    diesel::update(account::table.find(account_id))
+        .set((
+            account::mnemonic.eq(None::<String>),
+            account::mnemonic_ciphertext.eq(None::<Vec<u8>>),
+            account::mnemonic_data_key.eq(None::<Vec<u8>>),
+            account::mnemonic_key_version.eq(None::<i32>),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+// Function to get the plaintext mnemonic of an account, decrypting it if needed.
+// Rows not yet migrated by `encrypt-mnemonics` still carry the plaintext column.
+pub fn decrypt_mnemonic(account_data: &Account) -> Result<Option<SecretString>> {
+    match (
+        &account_data.mnemonic_ciphertext,
+        &account_data.mnemonic_data_key,
+        account_data.mnemonic_key_version,
+    ) {
+        (Some(ciphertext), Some(wrapped_key), Some(key_version)) => {
+            let envelope = Envelope {
+                ciphertext: ciphertext.clone(),
+                wrapped_key: wrapped_key.clone(),
+                key_version
+            };
+            let plaintext = open(&Keyring::from_env(MNEMONIC_KEY_PREFIX)?, &envelope, &account_key_aad(account_data.id))?;
+            Ok(Some(SecretString::try_from(plaintext)?))
+        },
+        (None, None, None) => Ok(account_data.mnemonic.clone()),
+        _ => Err(anyhow!("Incomplete mnemonic envelope for account {}", account_data.id))
+    }
+}
+
+// Function to load the master keys face templates are encrypted under. Load
+// them once for a scan over many rows.
+pub fn feature_keyring() -> Result<Keyring> {
+    Keyring::from_env(FEATURE_KEY_PREFIX)
+}
+
+// Function to get the plaintext face template of an account. Only the face
+// matching code paths should call this.
+pub fn decrypt_feature(keyring: &Keyring, account_data: &Account) -> Result<Option<Vec<u8>>> {
+    match (&account_data.feature, account_data.feature_key_version) {
+        (Some(sealed), Some(key_version)) => Ok(Some(decrypt_with_master(keyring, key_version, sealed)?)),
+        (template, None) => Ok(template.clone()),
+        (None, Some(_)) => Ok(None)
+    }
+}
+
+// Function to get the plaintext projection seed of an account's protected
+// template, if it has one.
+pub fn decrypt_template_seed(keyring: &Keyring, account_data: &Account) -> Result<Option<Vec<u8>>> {
+    match (&account_data.template_seed, account_data.template_seed_key_version) {
+        (Some(sealed), Some(key_version)) => Ok(Some(decrypt_with_master(keyring, key_version, sealed)?)),
+        (seed, None) => Ok(seed.clone()),
+        (None, Some(_)) => Ok(None)
+    }
+}
+
+// Function to encrypt every mnemonic still stored in plaintext, returning the number of rows migrated.
+pub fn encrypt_plaintext_mnemonics(conn: &mut PgConnection) -> Result<usize> {
+    let keyring = Keyring::from_env(MNEMONIC_KEY_PREFIX)?;
+    let rows = account::table
+        .filter(account::mnemonic.is_not_null())
+        .filter(account::mnemonic_ciphertext.is_null())
+        .load::<Account>(conn)?;
+
+    for row in &rows {
+        let phrase = row.mnemonic.as_ref().map(SecretString::expose_secret).unwrap_or_default();
+        let envelope = seal(&keyring, phrase.as_bytes(), &account_key_aad(row.id))?;
+        diesel::update(account::table.find(row.id))
+            .set((
+                account::mnemonic.eq(None::<String>),
+                account::mnemonic_ciphertext.eq(Some(envelope.ciphertext)),
+                account::mnemonic_data_key.eq(Some(envelope.wrapped_key)),
+                account::mnemonic_key_version.eq(Some(envelope.key_version)),
+            ))
+            .execute(conn)?;
+    }
+    Ok(rows.len())
+}
+
+// Function to get every account created before EVM addresses were linked.
+pub fn accounts_without_evm_address(conn: &mut PgConnection) -> Result<Vec<Account>> {
+    Ok(account::table
+        .filter(account::evm_address.is_null())
+        .load::<Account>(conn)?)
+}
+
+// Function to store the EVM address linked to an account.
+pub fn set_evm_address(conn: &mut PgConnection, account_id: i64, linked_evm_address: &str) -> Result<()> {
+    diesel::update(account::table.find(account_id))
+        .set(account::evm_address.eq(Some(linked_evm_address)))
+        .execute(conn)?;
+    Ok(())
+}
+
+// Function to record the account id of every account that has an address but
+// none stored yet, returning the number of rows updated.
+pub fn derive_public_keys(conn: &mut PgConnection) -> Result<usize> {
+    let rows = account::table
+        .filter(account::address.is_not_null())
+        .filter(account::public_key.is_null())
+        .load::<Account>(conn)?;
+
+    for row in &rows {
+        let account_public_key = address_public_key(row.address.as_deref().unwrap_or_default())?;
+        diesel::update(account::table.find(row.id))
+            .set(account::public_key.eq(Some(account_public_key)))
+            .execute(conn)?;
+    }
+    Ok(rows.len())
+}
+
+// Function to re-wrap every data key not under the current master key version,
+// returning the number of rows rotated.
+pub fn rotate_mnemonic_master_key(conn: &mut PgConnection) -> Result<usize> {
+    let keyring = Keyring::from_env(MNEMONIC_KEY_PREFIX)?;
+    let rows = account::table
+        .filter(account::mnemonic_key_version.ne(keyring.current_version()))
+        .load::<Account>(conn)?;
+
+    for row in &rows {
+        let envelope = Envelope {
+            ciphertext: row.mnemonic_ciphertext.clone().unwrap_or_default(),
+            wrapped_key: row.mnemonic_data_key.clone().unwrap_or_default(),
+            key_version: row.mnemonic_key_version.unwrap_or_default()
+        };
+        let rewrapped = rewrap(&keyring, &envelope)?;
+        diesel::update(account::table.find(row.id))
+            .set((
+                account::mnemonic_data_key.eq(Some(rewrapped.wrapped_key)),
+                account::mnemonic_key_version.eq(Some(rewrapped.key_version)),
+            ))
+            .execute(conn)?;
+    }
+
+    // Mnemonics replaced by a key rotation whose balance is not swept yet.
+    let rotations = key_rotation::table
+        .filter(key_rotation::old_mnemonic_key_version.ne(keyring.current_version()))
+        .load::<KeyRotation>(conn)?;
+
+    for rotation in &rotations {
+        let envelope = Envelope {
+            ciphertext: rotation.old_mnemonic_ciphertext.clone().unwrap_or_default(),
+            wrapped_key: rotation.old_mnemonic_data_key.clone().unwrap_or_default(),
+            key_version: rotation.old_mnemonic_key_version.unwrap_or_default()
+        };
+        let rewrapped = rewrap(&keyring, &envelope)?;
+        diesel::update(key_rotation::table.find(rotation.id))
+            .set((
+                key_rotation::old_mnemonic_data_key.eq(Some(rewrapped.wrapped_key)),
+                key_rotation::old_mnemonic_key_version.eq(Some(rewrapped.key_version)),
+            ))
+            .execute(conn)?;
+    }
+    Ok(rows.len() + rotations.len())
+}
+
+// Function to record the start of a key rotation. The replaced key is stored
+// on the row through the key vault before the account is switched over. Fails
+// with a unique violation while another rotation of the account is pending.
+pub fn create_key_rotation(conn: &mut PgConnection, account_data: &Account, new_address: &str) -> Result<KeyRotation> {
+    let new_rotation = NewKeyRotation {
+        account_id: account_data.id,
+        old_address: account_data.address.as_deref().unwrap_or_default(),
+        old_evm_address: account_data.evm_address.as_deref(),
+        new_address,
+        old_mnemonic_ciphertext: None,
+        old_mnemonic_data_key: None,
+        old_mnemonic_key_version: None
+    };
+
+    Ok(diesel::insert_into(key_rotation::table)
+        .values(&new_rotation)
+        .get_result(conn)?)
+}
+
+// Function to remove a key rotation that was abandoned before the account was
+// switched to its new key.
+pub fn delete_key_rotation(conn: &mut PgConnection, rotation_id: i64) -> usize {
+    diesel::delete(key_rotation::table.find(rotation_id))
+        .execute(conn)
+        .expect("Error deleting key rotation")
+}
+
+// Function to point an account and its sub-accounts at the addresses of its
+// new key, in one transaction. Applying a rotation twice is harmless, but an
+// account that has moved on to another address is left alone.
+pub fn apply_key_rotation(
+    conn: &mut PgConnection,
+    rotation: &KeyRotation,
+    evm_address: Option<&str>,
+    sub_addresses: &[(i64, String)]) -> Result<Account> {
+
+    let new_public_key = address_public_key(&rotation.new_address)?;
+    conn.transaction(|conn| {
+        let current = account::address.eq(&rotation.old_address).or(account::address.eq(&rotation.new_address));
+        let rotated = diesel::update(account::table.find(rotation.account_id).filter(current))
+            .set((
+                account::address.eq(Some(&rotation.new_address)),
+                account::public_key.eq(Some(&new_public_key)),
+                account::evm_address.eq(evm_address),
+            ))
+            .get_result::<Account>(conn)
+            .optional()?
+            .ok_or_else(|| anyhow!("Account {} was changed by another key rotation", rotation.account_id))?;
+
+        for (sub_account_id, sub_address) in sub_addresses {
+            diesel::update(sub_account::table.find(sub_account_id))
+                .set(sub_account::address.eq(sub_address))
+                .execute(conn)?;
+        }
+        Ok(rotated)
+    })
+}
+
+// Function to get the latest key rotation of an account whose old balance has
+// not been swept yet.
+pub fn pending_key_rotation(conn: &mut PgConnection, account_id: i64) -> Option<KeyRotation> {
+    key_rotation::table
+        .filter(key_rotation::account_id.eq(account_id))
+        .filter(key_rotation::swept_at.is_null())
+        .order(key_rotation::created_at.desc())
+        .first(conn)
+        .optional()
+        .expect("Error loading key rotation")
+}
+
+// Function to lock the row of a key rotation that is still pending, for the
+// rest of the current transaction.
+pub fn lock_pending_key_rotation(conn: &mut PgConnection, rotation_id: i64) -> Result<Option<KeyRotation>> {
+    Ok(key_rotation::table
+        .find(rotation_id)
+        .filter(key_rotation::swept_at.is_null())
+        .for_update()
+        .first(conn)
+        .optional()?)
+}
+
+// Function to check that `wallet_address` is still the address of an account
+// of `user_id`, as bearer tokens name the address they were issued for.
+pub fn is_current_wallet(conn: &mut PgConnection, user_id: i64, wallet_address: &str) -> Result<bool> {
+    let matching: i64 = account::table
+        .filter(account::uid.eq(user_id))
+        .filter(account::address.eq(wallet_address))
+        .count()
+        .get_result(conn)?;
+    Ok(matching > 0)
+}
+
+// Function to get a key rotation by its id.
+pub fn find_key_rotation(conn: &mut PgConnection, rotation_id: i64) -> Option<KeyRotation> {
+    key_rotation::table
+        .find(rotation_id)
+        .first(conn)
+        .optional()
+        .expect("Error loading key rotation")
+}
+
+// Function to get the replaced mnemonic of a pending key rotation.
+pub fn decrypt_rotated_mnemonic(rotation: &KeyRotation) -> Result<SecretString> {
+    let envelope = Envelope {
+        ciphertext: rotation.old_mnemonic_ciphertext.clone().unwrap_or_default(),
+        wrapped_key: rotation.old_mnemonic_data_key.clone().unwrap_or_default(),
+        key_version: rotation.old_mnemonic_key_version.unwrap_or_default()
+    };
+    let plaintext = open(&Keyring::from_env(MNEMONIC_KEY_PREFIX)?, &envelope, &rotation_key_aad(rotation.id))?;
+    SecretString::try_from(plaintext)
+}
+
+// Function to seal the replaced mnemonic of a key rotation onto its row.
+pub fn store_rotated_mnemonic(conn: &mut PgConnection, rotation_id: i64, phrase: &SecretString) -> Result<()> {
+    let envelope = seal(&Keyring::from_env(MNEMONIC_KEY_PREFIX)?, phrase.expose_secret().as_bytes(), &rotation_key_aad(rotation_id))?;
+    let updated = diesel::update(key_rotation::table.find(rotation_id))
+        .set((
+            key_rotation::old_mnemonic_ciphertext.eq(Some(envelope.ciphertext)),
+            key_rotation::old_mnemonic_data_key.eq(Some(envelope.wrapped_key)),
+            key_rotation::old_mnemonic_key_version.eq(Some(envelope.key_version)),
+        ))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(anyhow!("Key rotation {} does not exist", rotation_id));
+    }
+    Ok(())
+}
+
+// Function to drop the replaced mnemonic of a key rotation.
+pub fn clear_rotated_mnemonic(conn: &mut PgConnection, rotation_id: i64) -> Result<()> {
+    diesel::update(key_rotation::table.find(rotation_id))
+        .set((
+            key_rotation::old_mnemonic_ciphertext.eq(None::<Vec<u8>>),
+            key_rotation::old_mnemonic_data_key.eq(None::<Vec<u8>>),
+            key_rotation::old_mnemonic_key_version.eq(None::<i32>),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+// Function to mark a key rotation as swept. The old key is deleted from the
+// key vault first.
+pub fn complete_key_rotation(conn: &mut PgConnection, rotation_id: i64, now: NaiveDateTime) -> KeyRotation {
+    diesel::update(key_rotation::table.find(rotation_id))
+        .set(key_rotation::swept_at.eq(Some(now)))
+        .get_result(conn)
+        .expect("Error completing key rotation")
+}
+
+// Function to replace the face template of an account, e.g. when a protected
+// template is re-issued under a new seed.
+pub fn update_template(
+    conn: &mut PgConnection,
+    account_id: i64,
+    feature: &FaceTemplate,
+    template_seed: Option<&[u8]>) -> Result<Account> {
+
+    let keyring = feature_keyring()?;
+    let (sealed, key_version) = encrypt_with_master(&keyring, &feature.encode())?;
+    let sealed_seed = match template_seed {
+        Some(seed) => Some(encrypt_with_master(&keyring, seed)?),
+        None => None
+    };
+
+    Ok(diesel::update(account::table.find(account_id))
+        .set((
+            account::feature.eq(Some(sealed)),
+            account::feature_key_version.eq(Some(key_version)),
+            account::template_seed.eq(sealed_seed.as_ref().map(|(sealed, _)| sealed.as_slice())),
+            account::template_seed_key_version.eq(sealed_seed.as_ref().map(|(_, version)| *version)),
+            account::feature_model.eq(Some(&feature.model_id)),
+        ))
+        .get_result(conn)
+        .expect("Error updating face template"))
+}
+
+// Function to flag an account as a probable duplicate enrollment of another one.
+pub fn flag_duplicate_enrollment(conn: &mut PgConnection, account_id: i64, duplicate_of: i64) -> Account {
+    diesel::update(account::table.find(account_id))
+        .set(account::duplicate_of.eq(Some(duplicate_of)))
+        .get_result(conn)
+        .expect("Error flagging duplicate enrollment")
+}
+
+// Function to record the score of the latest face verification against an account.
+pub fn update_match_score(conn: &mut PgConnection, account_id: i64, score: f32) -> Account {
+    diesel::update(account::table.find(account_id))
+        .set(account::last_match_score.eq(Some(score)))
+        .get_result(conn)
+        .expect("Error updating match score")
+}
+
+// Function to issue a single-use mnemonic reveal ticket for an account.
+pub fn create_reveal_ticket(
+    conn: &mut PgConnection,
+    account_id: i64,
+    ticket: &str,
+    expires_at: NaiveDateTime) -> RevealTicket {
+
+    let new_ticket = NewRevealTicket {
+        account_id,
+        ticket,
+        expires_at
+    };
+
+    diesel::insert_into(reveal_ticket::table)
+        .values(&new_ticket)
         .get_result(conn)
-        .expect("Error saving new account")
+        .expect("Error saving new reveal ticket")
+}
+
+// Function to look up a reveal ticket regardless of its state.
+pub fn find_reveal_ticket(conn: &mut PgConnection, ticket: &str) -> Option<RevealTicket> {
+    reveal_ticket::table
+        .filter(reveal_ticket::ticket.eq(ticket))
+        .first(conn)
+        .optional()
+        .expect("Error loading reveal ticket")
+}
+
+// Function to redeem a reveal ticket. The ticket is marked used in the same
+// statement that checks it, so concurrent redemptions cannot both succeed.
+// Run it in a transaction together with `create_reveal_audit`, so a ticket is
+// never consumed without its audit entry.
+pub fn redeem_reveal_ticket(
+    conn: &mut PgConnection,
+    ticket: &str,
+    now: NaiveDateTime) -> Result<Option<RevealTicket>> {
+
+    Ok(diesel::update(
+        reveal_ticket::table
+            .filter(reveal_ticket::ticket.eq(ticket))
+            .filter(reveal_ticket::used_at.is_null())
+            .filter(reveal_ticket::expires_at.gt(now)),
+    )
+    .set(reveal_ticket::used_at.eq(Some(now)))
+    .get_result(conn)
+    .optional()?)
+}
+
+// Function to append an entry to the mnemonic reveal audit log.
+pub fn create_reveal_audit(
+    conn: &mut PgConnection,
+    account_id: i64,
+    ticket_id: Option<i64>,
+    outcome: &str,
+    ip: Option<&str>,
+    user_agent: Option<&str>) -> Result<()> {
+
+    let new_audit = NewMnemonicRevealAudit {
+        account_id,
+        ticket_id,
+        outcome,
+        ip,
+        user_agent
+    };
+
+    diesel::insert_into(mnemonic_reveal_audit::table)
+        .values(&new_audit)
+        .execute(conn)?;
+    Ok(())
+}
+
+// Function to get the time of the latest successful mnemonic reveal for an account.
+pub fn last_mnemonic_reveal(conn: &mut PgConnection, account_id: i64) -> Option<NaiveDateTime> {
+    mnemonic_reveal_audit::table
+        .filter(mnemonic_reveal_audit::account_id.eq(account_id))
+        .filter(mnemonic_reveal_audit::outcome.eq(REVEAL_OUTCOME_REVEALED))
+        .select(mnemonic_reveal_audit::created_at)
+        .order(mnemonic_reveal_audit::created_at.desc())
+        .first(conn)
+        .optional()
+        .expect("Error loading reveal audit")
+}
+
+// Function to append an entry to the signature audit log. Only the hash of
+// the signed payload is recorded.
+pub fn create_signature_audit(conn: &mut PgConnection, new_audit: &NewSignatureAudit) -> Result<()> {
+    diesel::insert_into(signature_audit::table)
+        .values(new_audit)
+        .execute(conn)?;
+    Ok(())
+}
+
+// Function to issue a login challenge that the account's key must sign.
+pub fn create_auth_challenge(
+    conn: &mut PgConnection,
+    account_id: i64,
+    nonce: &str,
+    expires_at: NaiveDateTime) -> AuthChallenge {
+
+    let new_challenge = NewAuthChallenge {
+        account_id,
+        nonce,
+        expires_at
+    };
+
+    diesel::insert_into(auth_challenge::table)
+        .values(&new_challenge)
+        .get_result(conn)
+        .expect("Error saving new auth challenge")
+}
+
+// Function to consume a login challenge of an account. Like reveal tickets it is
+// marked used in the same statement that checks it, so a nonce is only ever
+// accepted once, whether or not the signature over it turns out valid.
+pub fn redeem_auth_challenge(
+    conn: &mut PgConnection,
+    account_id: i64,
+    nonce: &str,
+    now: NaiveDateTime) -> Option<AuthChallenge> {
+
+    diesel::update(
+        auth_challenge::table
+            .filter(auth_challenge::account_id.eq(account_id))
+            .filter(auth_challenge::nonce.eq(nonce))
+            .filter(auth_challenge::used_at.is_null())
+            .filter(auth_challenge::expires_at.gt(now)),
+    )
+    .set(auth_challenge::used_at.eq(Some(now)))
+    .get_result(conn)
+    .optional()
+    .expect("Error redeeming auth challenge")
+}
+
+// Function to issue a nonce for a Sign-In with Ethereum message. It is not tied
+// to an account: the signer is only known once the message comes back.
+pub fn create_siwe_nonce(conn: &mut PgConnection, nonce: &str, expires_at: NaiveDateTime) -> SiweNonce {
+    let new_nonce = NewSiweNonce {
+        nonce,
+        expires_at
+    };
+
+    diesel::insert_into(siwe_nonce::table)
+        .values(&new_nonce)
+        .get_result(conn)
+        .expect("Error saving new SIWE nonce")
+}
+
+// Function to consume a SIWE nonce, checked and marked used in one statement.
+pub fn redeem_siwe_nonce(conn: &mut PgConnection, nonce: &str, now: NaiveDateTime) -> Option<SiweNonce> {
+    diesel::update(
+        siwe_nonce::table
+            .filter(siwe_nonce::nonce.eq(nonce))
+            .filter(siwe_nonce::used_at.is_null())
+            .filter(siwe_nonce::expires_at.gt(now)),
+    )
+    .set(siwe_nonce::used_at.eq(Some(now)))
+    .get_result(conn)
+    .optional()
+    .expect("Error redeeming SIWE nonce")
+}
+
+// Function to issue a challenge for proving ownership of an imported key. Like
+// the SIWE nonce it has no account yet: the key is enrolled once it is redeemed.
+// The challenge can only be answered for `address`.
+pub fn create_import_challenge(conn: &mut PgConnection, nonce: &str, address: &str, expires_at: NaiveDateTime) -> ImportChallenge {
+    let new_challenge = NewImportChallenge {
+        nonce,
+        expires_at,
+        address
+    };
+
+    diesel::insert_into(import_challenge::table)
+        .values(&new_challenge)
+        .get_result(conn)
+        .expect("Error saving new import challenge")
+}
+
+// Function to get an import challenge that is still unused and unexpired,
+// without consuming it.
+pub fn find_import_challenge(conn: &mut PgConnection, nonce: &str, now: NaiveDateTime) -> Option<ImportChallenge> {
+    import_challenge::table
+        .filter(import_challenge::nonce.eq(nonce))
+        .filter(import_challenge::used_at.is_null())
+        .filter(import_challenge::expires_at.gt(now))
+        .first(conn)
+        .optional()
+        .expect("Error loading import challenge")
+}
+
+// Function to consume an import challenge, checked and marked used in one statement.
+pub fn redeem_import_challenge(conn: &mut PgConnection, nonce: &str, now: NaiveDateTime) -> Option<ImportChallenge> {
+    diesel::update(
+        import_challenge::table
+            .filter(import_challenge::nonce.eq(nonce))
+            .filter(import_challenge::used_at.is_null())
+            .filter(import_challenge::expires_at.gt(now)),
+    )
+    .set(import_challenge::used_at.eq(Some(now)))
+    .get_result(conn)
+    .optional()
+    .expect("Error redeeming import challenge")
+}
+
+// Function to store the hash of a newly issued refresh token.
+pub fn create_refresh_token(
+    conn: &mut PgConnection,
+    account_id: i64,
+    family_id: &str,
+    token_hash: &str,
+    expires_at: NaiveDateTime) -> RefreshToken {
+
+    let new_token = NewRefreshToken {
+        account_id,
+        family_id,
+        token_hash,
+        expires_at
+    };
+
+    diesel::insert_into(refresh_token::table)
+        .values(&new_token)
+        .get_result(conn)
+        .expect("Error saving new refresh token")
+}
+
+// Why a refresh token cannot be exchanged.
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenRejection {
+    // Exchanged before: either the client or a thief replayed it.
+    Reused,
+    Revoked,
+    Expired,
+}
+
+impl RefreshTokenRejection {
+    // A replayed token cannot tell the thief from the client, so the whole
+    // family is signed out.
+    pub fn revokes_family(&self) -> bool {
+        *self == RefreshTokenRejection::Reused
+    }
+}
+
+// Function to tell whether a refresh token can be exchanged at `now`. A used
+// token counts as reused even once its family has been revoked.
+pub fn check_refresh_token(token: &RefreshToken, now: NaiveDateTime) -> std::result::Result<(), RefreshTokenRejection> {
+    if token.used_at.is_some() {
+        Err(RefreshTokenRejection::Reused)
+    } else if token.revoked_at.is_some() {
+        Err(RefreshTokenRejection::Revoked)
+    } else if token.expires_at <= now {
+        Err(RefreshTokenRejection::Expired)
+    } else {
+        Ok(())
+    }
+}
+
+// Outcome of presenting a refresh token for exchange.
+pub enum Redemption {
+    Redeemed(RefreshToken),
+    Rejected(RefreshToken, RefreshTokenRejection),
+    // Never issued.
+    Unknown,
+}
+
+// Function to consume a refresh token when it is exchanged for a new one. The
+// row is locked while it is checked, so of two concurrent refreshes with the
+// same token only one succeeds.
+pub fn redeem_refresh_token(conn: &mut PgConnection, token_hash: &str, now: NaiveDateTime) -> Redemption {
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let token = match refresh_token::table
+            .filter(refresh_token::token_hash.eq(token_hash))
+            .for_update()
+            .first::<RefreshToken>(conn)
+            .optional()?
+        {
+            Some(t) => t,
+            None => return Ok(Redemption::Unknown)
+        };
+        if let Err(rejection) = check_refresh_token(&token, now) {
+            return Ok(Redemption::Rejected(token, rejection));
+        }
+        let redeemed = diesel::update(refresh_token::table.find(token.id))
+            .set(refresh_token::used_at.eq(Some(now)))
+            .get_result(conn)?;
+        Ok(Redemption::Redeemed(redeemed))
+    })
+    .expect("Error redeeming refresh token")
+}
+
+// Function to record an issued access token as a session of an account.
+pub fn create_session(
+    conn: &mut PgConnection,
+    account_id: i64,
+    jti: &str,
+    family_id: &str,
+    device: Option<&str>,
+    ip: Option<&str>,
+    expires_at: NaiveDateTime) -> Result<Session> {
+
+    let new_session = NewSession {
+        account_id,
+        jti,
+        family_id,
+        device,
+        ip,
+        expires_at
+    };
+
+    Ok(diesel::insert_into(session::table)
+        .values(&new_session)
+        .get_result(conn)?)
+}
+
+// Function to look up the session of an access token by its `jti`.
+pub fn find_session_by_jti(conn: &mut PgConnection, jti: &str) -> Option<Session> {
+    session::table
+        .filter(session::jti.eq(jti))
+        .first(conn)
+        .optional()
+        .expect("Error loading session")
+}
+
+// Function to get a session of an account by its id.
+pub fn find_account_session(conn: &mut PgConnection, account_id: i64, session_id: i64) -> Option<Session> {
+    session::table
+        .filter(session::id.eq(session_id))
+        .filter(session::account_id.eq(account_id))
+        .first(conn)
+        .optional()
+        .expect("Error loading session")
+}
+
+// Function to list the signed-in sessions of an account, newest first. A login
+// counts while it is not revoked and either its access token or its refresh
+// token is still usable; only its latest access token is listed.
+pub fn list_active_sessions(conn: &mut PgConnection, account_id: i64, now: NaiveDateTime) -> Vec<Session> {
+    let live_families = refresh_token::table
+        .filter(refresh_token::account_id.eq(account_id))
+        .filter(refresh_token::used_at.is_null())
+        .filter(refresh_token::revoked_at.is_null())
+        .filter(refresh_token::expires_at.gt(now))
+        .select(refresh_token::family_id)
+        .load::<String>(conn)
+        .expect("Error loading refresh tokens");
+
+    let mut listed = HashSet::new();
+    session::table
+        .filter(session::account_id.eq(account_id))
+        .filter(session::revoked_at.is_null())
+        .filter(session::expires_at.gt(now).or(session::family_id.eq_any(&live_families)))
+        .order(session::created_at.desc())
+        .load::<Session>(conn)
+        .expect("Error loading sessions")
+        .into_iter()
+        .filter(|t| listed.insert(t.family_id.clone()))
+        .collect()
+}
+
+// Function to sign out a login: every session and refresh token of the token
+// family is revoked in one transaction, and the access tokens are rejected from
+// then on. Returns the number of access tokens revoked.
+pub fn revoke_session_family(conn: &mut PgConnection, family_id: &str, now: NaiveDateTime) -> usize {
+    let revoked = conn.transaction::<Vec<Session>, diesel::result::Error, _>(|conn| {
+        diesel::update(
+            refresh_token::table
+                .filter(refresh_token::family_id.eq(family_id))
+                .filter(refresh_token::revoked_at.is_null()),
+        )
+        .set(refresh_token::revoked_at.eq(Some(now)))
+        .execute(conn)?;
+
+        diesel::update(
+            session::table
+                .filter(session::family_id.eq(family_id))
+                .filter(session::revoked_at.is_null()),
+        )
+        .set(session::revoked_at.eq(Some(now)))
+        .get_results(conn)
+    })
+    .expect("Error revoking session");
+    cache_revoked_sessions(&revoked)
+}
+
+// Function to sign out every login of an account, e.g. once its key has been
+// rotated. Returns the number of access tokens revoked.
+pub fn revoke_account_sessions(conn: &mut PgConnection, account_id: i64, now: NaiveDateTime) -> usize {
+    let revoked = conn.transaction::<Vec<Session>, diesel::result::Error, _>(|conn| {
+        diesel::update(
+            refresh_token::table
+                .filter(refresh_token::account_id.eq(account_id))
+                .filter(refresh_token::revoked_at.is_null()),
+        )
+        .set(refresh_token::revoked_at.eq(Some(now)))
+        .execute(conn)?;
+
+        diesel::update(
+            session::table
+                .filter(session::account_id.eq(account_id))
+                .filter(session::revoked_at.is_null()),
+        )
+        .set(session::revoked_at.eq(Some(now)))
+        .get_results(conn)
+    })
+    .expect("Error revoking sessions");
+    cache_revoked_sessions(&revoked)
+}
+
+// Function to load the revoked sessions whose access token has not expired yet
+// into the token revocation cache, returning their number. Run at startup and
+// then periodically, so that revocations made by other server instances apply.
+pub fn sync_revoked_sessions(conn: &mut PgConnection, now: NaiveDateTime) -> usize {
+    let revoked = session::table
+        .filter(session::revoked_at.is_not_null())
+        .filter(session::expires_at.gt(now))
+        .load::<Session>(conn)
+        .expect("Error loading revoked sessions");
+    cache_revoked_sessions(&revoked)
+}
+
+fn cache_revoked_sessions(revoked: &[Session]) -> usize {
+    revoke_tokens(revoked.iter().map(|t| (t.jti.clone(), t.expires_at.and_utc().timestamp() as usize)));
+    revoked.len()
+}
+
+// Function to record a derived sub-account. Fails when the wallet already has a
+// sub-account with the same name or derivation path.
+pub fn create_sub_account(
+    conn: &mut PgConnection,
+    account_id: i64,
+    name: &str,
+    derivation_path: &str,
+    address: &str) -> Result<SubAccount> {
+
+    let new_sub_account = NewSubAccount {
+        account_id,
+        name,
+        derivation_path,
+        address
+    };
+
+    Ok(diesel::insert_into(sub_account::table)
+        .values(&new_sub_account)
+        .get_result(conn)?)
+}
+
+// Function to rename the sub-account of a wallet at `derivation_path`.
+pub fn rename_sub_account(
+    conn: &mut PgConnection,
+    account_id: i64,
+    derivation_path: &str,
+    name: &str) -> Result<Option<SubAccount>> {
+
+    Ok(diesel::update(
+        sub_account::table
+            .filter(sub_account::account_id.eq(account_id))
+            .filter(sub_account::derivation_path.eq(derivation_path)),
+    )
+    .set(sub_account::name.eq(name))
+    .get_result(conn)
+    .optional()?)
+}
+
+// Function to look up a sub-account of a wallet by name.
+pub fn find_sub_account(conn: &mut PgConnection, account_id: i64, name: &str) -> Option<SubAccount> {
+    sub_account::table
+        .filter(sub_account::account_id.eq(account_id))
+        .filter(sub_account::name.eq(name))
+        .first(conn)
+        .optional()
+        .expect("Error loading sub-account")
+}
+
+// Function to list the sub-accounts of a wallet in creation order.
+pub fn list_sub_accounts(conn: &mut PgConnection, account_id: i64) -> Vec<SubAccount> {
+    sub_account::table
+        .filter(sub_account::account_id.eq(account_id))
+        .order(sub_account::id.asc())
+        .load(conn)
+        .expect("Error loading sub-accounts")
+}
+
+// Function to encrypt every face template and template seed still stored in
+// plaintext and re-encrypt those under an old master key version, returning the
+// number of rows updated.
+pub fn encrypt_features(conn: &mut PgConnection) -> Result<usize> {
+    let keyring = feature_keyring()?;
+    let rows = account::table
+        .filter(account::feature.is_not_null())
+        .filter(
+            account::feature_key_version
+                .is_null()
+                .or(account::feature_key_version.ne(keyring.current_version()))
+                .or(account::template_seed.is_not_null().and(
+                    account::template_seed_key_version
+                        .is_null()
+                        .or(account::template_seed_key_version.ne(keyring.current_version())),
+                )),
+        )
+        .load::<Account>(conn)?;
+
+    for row in &rows {
+        let template = decrypt_feature(&keyring, row)?.unwrap_or_default();
+        let (sealed, key_version) = encrypt_with_master(&keyring, &template)?;
+        let sealed_seed = match decrypt_template_seed(&keyring, row)? {
+            Some(seed) => Some(encrypt_with_master(&keyring, &seed)?),
+            None => None
+        };
+        diesel::update(account::table.find(row.id))
+            .set((
+                account::feature.eq(Some(sealed)),
+                account::feature_key_version.eq(Some(key_version)),
+                account::template_seed.eq(sealed_seed.as_ref().map(|(sealed, _)| sealed.as_slice())),
+                account::template_seed_key_version.eq(sealed_seed.as_ref().map(|(_, version)| *version)),
+            ))
+            .execute(conn)?;
+    }
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use diesel::result::{DatabaseErrorKind, Error};
+
+    #[test]
+    fn test_is_unique_violation() {
+        let duplicate = Error::DatabaseError(DatabaseErrorKind::UniqueViolation, Box::new("duplicate key".to_string()));
+        assert!(is_unique_violation(&duplicate.into()));
+
+        let other = Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, Box::new("missing key".to_string()));
+        assert!(!is_unique_violation(&other.into()));
+        assert!(!is_unique_violation(&Error::NotFound.into()));
+        assert!(!is_unique_violation(&anyhow!("not a database error")));
+    }
+
+    fn refresh_token_at(now: NaiveDateTime) -> RefreshToken {
+        RefreshToken {
+            id: 1,
+            account_id: 1,
+            family_id: "family".to_string(),
+            token_hash: "hash".to_string(),
+            expires_at: now + chrono::Duration::days(30),
+            used_at: None,
+            revoked_at: None,
+            created_at: now
+        }
+    }
+
+    #[test]
+    fn test_refresh_token_single_use() {
+        let now = chrono::Utc::now().naive_utc();
+        let token = refresh_token_at(now);
+        assert_eq!(check_refresh_token(&token, now), Ok(()));
+
+        let used = RefreshToken { used_at: Some(now), ..token };
+        assert_eq!(check_refresh_token(&used, now), Err(RefreshTokenRejection::Reused));
+    }
+
+    #[test]
+    fn test_refresh_token_expired() {
+        let now = chrono::Utc::now().naive_utc();
+        let token = refresh_token_at(now);
+        let later = token.expires_at;
+        assert_eq!(check_refresh_token(&token, later), Err(RefreshTokenRejection::Expired));
+
+        let revoked = RefreshToken { revoked_at: Some(now), ..token };
+        assert_eq!(check_refresh_token(&revoked, now), Err(RefreshTokenRejection::Revoked));
+    }
+
+    #[test]
+    fn test_refresh_token_reuse_revokes_family() {
+        let now = chrono::Utc::now().naive_utc();
+        // Replayed after its family was already revoked, and after expiry.
+        let replayed = RefreshToken {
+            used_at: Some(now),
+            revoked_at: Some(now),
+            ..refresh_token_at(now)
+        };
+        let rejection = check_refresh_token(&replayed, replayed.expires_at).unwrap_err();
+        assert_eq!(rejection, RefreshTokenRejection::Reused);
+        assert!(rejection.revokes_family());
+
+        assert!(!RefreshTokenRejection::Revoked.revokes_family());
+        assert!(!RefreshTokenRejection::Expired.revokes_family());
+    }
 }
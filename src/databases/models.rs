@@ -1,25 +1,303 @@
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use crate::schema::account;
+use crate::schema::{
+    account, auth_challenge, import_challenge, key_rotation, mnemonic_reveal_audit,
+    refresh_token, reveal_ticket, session, signature_audit, siwe_nonce, sub_account,
+};
 use diesel::sql_types::Bytea; // Include Bytea type for handling binary data
+use crate::face::template::FaceTemplate;
+use crate::secret::SecretString;
 
-#[derive(Clone, Debug, Queryable, Selectable)]
+// Not `Debug`: rows written before mnemonics were encrypted still carry the
+// plaintext `mnemonic`.
+#[derive(Clone, Queryable, Selectable)]
 #[diesel(table_name = account)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Account {
     pub id: i64,
     pub uid: i64,
-    pub mnemonic: Option<String>,
+    pub mnemonic: Option<SecretString>,
     pub address: Option<String>,
     pub token: Option<String>,
     pub feature: Option<Vec<u8>>,  // Include the feature field for binary data
+    pub last_match_score: Option<f32>,
+    pub mnemonic_ciphertext: Option<Vec<u8>>,
+    pub mnemonic_data_key: Option<Vec<u8>>,
+    pub mnemonic_key_version: Option<i32>,
+    pub feature_key_version: Option<i32>,
+    pub template_seed: Option<Vec<u8>>,
+    pub duplicate_of: Option<i64>,
+    pub feature_model: Option<String>,
+    pub evm_address: Option<String>,
+    pub key_scheme: String,
+    pub ss58_prefix: i32,
+    pub template_seed_key_version: Option<i32>,
+    pub secret_kind: String,
+    // The 32-byte account id `address` encodes: the public key, or its blake2
+    // hash for ECDSA. Unlike the address it does not depend on the SS58 prefix.
+    pub public_key: Option<Vec<u8>>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = account)]
 pub struct NewAccount<'a> {
     pub uid: i64,
-    pub mnemonic: Option<&'a str>,
     pub address: Option<&'a str>,
+    pub evm_address: Option<&'a str>,
+    pub token: Option<&'a str>,
+    pub feature: Option<&'a [u8]>,  // Include the feature field to be able to insert binary data
+    pub mnemonic_ciphertext: Option<&'a [u8]>,
+    pub mnemonic_data_key: Option<&'a [u8]>,
+    pub mnemonic_key_version: Option<i32>,
+    pub feature_key_version: Option<i32>,
+    pub template_seed: Option<&'a [u8]>,
+    pub feature_model: Option<&'a str>,
+    pub key_scheme: &'a str,
+    pub ss58_prefix: i32,
+    pub template_seed_key_version: Option<i32>,
+    pub secret_kind: &'a str,
+    pub public_key: Option<&'a [u8]>,
+}
+
+// A new account as handed to `create_account`, which encrypts the face template
+// and its seed into a `NewAccount` before inserting it.
+pub struct NewAccountData<'a> {
+    pub uid: i64,
+    pub address: Option<&'a str>,
+    pub evm_address: Option<&'a str>,
     pub token: Option<&'a str>,
-    pub feature: Option<&'a [u8]>  // Include the feature field to be able to insert binary data
+    pub feature: Option<&'a FaceTemplate>,
+    pub template_seed: Option<&'a [u8]>,
+    pub key_scheme: &'a str,
+    pub ss58_prefix: i32,
+    pub secret_kind: &'a str,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = reveal_ticket)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RevealTicket {
+    pub id: i64,
+    pub account_id: i64,
+    pub ticket: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = reveal_ticket)]
+pub struct NewRevealTicket<'a> {
+    pub account_id: i64,
+    pub ticket: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = mnemonic_reveal_audit)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MnemonicRevealAudit {
+    pub id: i64,
+    pub account_id: i64,
+    pub ticket_id: Option<i64>,
+    pub outcome: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = mnemonic_reveal_audit)]
+pub struct NewMnemonicRevealAudit<'a> {
+    pub account_id: i64,
+    pub ticket_id: Option<i64>,
+    pub outcome: &'a str,
+    pub ip: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = key_rotation)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct KeyRotation {
+    pub id: i64,
+    pub account_id: i64,
+    pub old_address: String,
+    pub old_evm_address: Option<String>,
+    pub new_address: String,
+    pub old_mnemonic_ciphertext: Option<Vec<u8>>,
+    pub old_mnemonic_data_key: Option<Vec<u8>>,
+    pub old_mnemonic_key_version: Option<i32>,
+    pub swept_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = key_rotation)]
+pub struct NewKeyRotation<'a> {
+    pub account_id: i64,
+    pub old_address: &'a str,
+    pub old_evm_address: Option<&'a str>,
+    pub new_address: &'a str,
+    pub old_mnemonic_ciphertext: Option<&'a [u8]>,
+    pub old_mnemonic_data_key: Option<&'a [u8]>,
+    pub old_mnemonic_key_version: Option<i32>,
+}
+
+// Holds only the hash of the token, so it is safe to log.
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = refresh_token)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RefreshToken {
+    pub id: i64,
+    pub account_id: i64,
+    pub family_id: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = refresh_token)]
+pub struct NewRefreshToken<'a> {
+    pub account_id: i64,
+    pub family_id: &'a str,
+    pub token_hash: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = session)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: i64,
+    pub account_id: i64,
+    pub jti: String,
+    pub family_id: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = session)]
+pub struct NewSession<'a> {
+    pub account_id: i64,
+    pub jti: &'a str,
+    pub family_id: &'a str,
+    pub device: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = signature_audit)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SignatureAudit {
+    pub id: i64,
+    pub account_id: i64,
+    pub sub_account_id: Option<i64>,
+    pub scope: String,
+    pub payload_hash: String,
+    pub payload_len: i32,
+    pub face_verified: bool,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = signature_audit)]
+pub struct NewSignatureAudit<'a> {
+    pub account_id: i64,
+    pub sub_account_id: Option<i64>,
+    pub scope: &'a str,
+    pub payload_hash: &'a str,
+    pub payload_len: i32,
+    pub face_verified: bool,
+    pub ip: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = auth_challenge)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuthChallenge {
+    pub id: i64,
+    pub account_id: i64,
+    pub nonce: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = auth_challenge)]
+pub struct NewAuthChallenge<'a> {
+    pub account_id: i64,
+    pub nonce: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = import_challenge)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ImportChallenge {
+    pub id: i64,
+    pub nonce: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub address: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = import_challenge)]
+pub struct NewImportChallenge<'a> {
+    pub nonce: &'a str,
+    pub expires_at: NaiveDateTime,
+    pub address: &'a str,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = siwe_nonce)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SiweNonce {
+    pub id: i64,
+    pub nonce: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = siwe_nonce)]
+pub struct NewSiweNonce<'a> {
+    pub nonce: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = sub_account)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SubAccount {
+    pub id: i64,
+    pub account_id: i64,
+    pub name: String,
+    pub derivation_path: String,
+    pub address: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sub_account)]
+pub struct NewSubAccount<'a> {
+    pub account_id: i64,
+    pub name: &'a str,
+    pub derivation_path: &'a str,
+    pub address: &'a str,
 }
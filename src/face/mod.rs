@@ -0,0 +1,362 @@
+use anyhow::{bail, Result};
+use dotenvy::dotenv;
+use std::env;
+use std::str::FromStr;
+
+pub mod protection;
+pub mod template;
+
+use protection::project;
+use template::FaceTemplate;
+
+// Default thresholds follow the usual operating points for 128-d dlib
+// embeddings (L2) and normalized embeddings (cosine).
+const DEFAULT_L2_THRESHOLD: f32 = 0.6;
+const DEFAULT_COSINE_THRESHOLD: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Cosine,
+    L2,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Cosine => "cosine",
+            Metric::L2 => "l2",
+        }
+    }
+
+    pub fn default_threshold(&self) -> f32 {
+        match self {
+            Metric::Cosine => DEFAULT_COSINE_THRESHOLD,
+            Metric::L2 => DEFAULT_L2_THRESHOLD,
+        }
+    }
+
+    /// Cosine is a similarity (higher is closer), L2 a distance (lower is closer).
+    pub fn accepts(&self, score: f32, threshold: f32) -> bool {
+        match self {
+            Metric::Cosine => score >= threshold,
+            Metric::L2 => score <= threshold,
+        }
+    }
+
+    fn is_better(&self, score: f32, than: f32) -> bool {
+        match self {
+            Metric::Cosine => score > than,
+            Metric::L2 => score < than,
+        }
+    }
+
+    pub fn score(&self, a: &[f32], b: &[f32]) -> Result<f32> {
+        if a.len() != b.len() {
+            bail!("Embedding dimensions differ: {} vs {}", a.len(), b.len());
+        }
+        if a.is_empty() {
+            bail!("Empty embedding");
+        }
+        match self {
+            Metric::Cosine => {
+                let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    bail!("Zero-norm embedding");
+                }
+                Ok(dot / (norm_a * norm_b))
+            }
+            Metric::L2 => Ok(a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt()),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "cosine" => Ok(Metric::Cosine),
+            "l2" | "euclidean" => Ok(Metric::L2),
+            _ => bail!("Unknown face match metric: {}", s),
+        }
+    }
+}
+
+/// Element type of the raw `feature` bytes sent by the Python server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeatureDtype {
+    F32,
+    F64,
+}
+
+impl FromStr for FeatureDtype {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "f32" | "float32" => Ok(FeatureDtype::F32),
+            "f64" | "float64" => Ok(FeatureDtype::F64),
+            _ => bail!("Unknown face feature dtype: {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MatchConfig {
+    pub metric: Metric,
+    pub threshold: f32,
+    pub dtype: FeatureDtype,
+}
+
+impl MatchConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv().ok();
+
+        let metric: Metric = env::var("FACE_MATCH_METRIC")
+            .unwrap_or_else(|_| "l2".to_string())
+            .parse()?;
+        let threshold = match env::var("FACE_MATCH_THRESHOLD") {
+            Ok(value) => value.parse::<f32>()?,
+            Err(_) => metric.default_threshold(),
+        };
+        let dtype: FeatureDtype = env::var("FACE_FEATURE_DTYPE")
+            .unwrap_or_else(|_| "f64".to_string())
+            .parse()?;
+
+        Ok(MatchConfig {
+            metric,
+            threshold,
+            dtype,
+        })
+    }
+}
+
+/// What `create_wallet_post` does when a new face is too close to an enrolled one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicatePolicy {
+    Reject,
+    Flag,
+    Allow,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(DuplicatePolicy::Reject),
+            "flag" => Ok(DuplicatePolicy::Flag),
+            "allow" => Ok(DuplicatePolicy::Allow),
+            _ => bail!("Unknown duplicate enrollment policy: {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DuplicateConfig {
+    pub policy: DuplicatePolicy,
+    pub threshold: f32,
+}
+
+impl DuplicateConfig {
+    /// The threshold defaults to the one used for matching.
+    pub fn from_env(match_config: &MatchConfig) -> Result<Self> {
+        dotenv().ok();
+
+        let policy: DuplicatePolicy = env::var("ENROLL_DUPLICATE_POLICY")
+            .unwrap_or_else(|_| "reject".to_string())
+            .parse()?;
+        let threshold = match env::var("ENROLL_DUPLICATE_THRESHOLD") {
+            Ok(value) => value.parse::<f32>()?,
+            Err(_) => match_config.threshold,
+        };
+
+        Ok(DuplicateConfig { policy, threshold })
+    }
+
+    /// Whether a face scoring `score` against its closest enrolled template is a
+    /// duplicate enrollment. Never the case under `Allow`.
+    pub fn is_duplicate(&self, metric: Metric, score: f32) -> bool {
+        self.policy != DuplicatePolicy::Allow && metric.accepts(score, self.threshold)
+    }
+}
+
+/// Decodes little-endian feature bytes (numpy `tobytes()`) into an f32 embedding.
+pub fn decode_feature(bytes: &[u8], dtype: FeatureDtype) -> Result<Vec<f32>> {
+    let embedding: Vec<f32> = match dtype {
+        FeatureDtype::F32 => {
+            if bytes.is_empty() || !bytes.len().is_multiple_of(4) {
+                bail!("Invalid f32 feature length: {}", bytes.len());
+            }
+            bytes
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect()
+        }
+        FeatureDtype::F64 => {
+            if bytes.is_empty() || !bytes.len().is_multiple_of(8) {
+                bail!("Invalid f64 feature length: {}", bytes.len());
+            }
+            bytes
+                .chunks_exact(8)
+                .map(|c| {
+                    let mut buf = [0u8; 8];
+                    buf.copy_from_slice(c);
+                    f64::from_le_bytes(buf) as f32
+                })
+                .collect()
+        }
+    };
+    if embedding.iter().any(|x| !x.is_finite()) {
+        bail!("Feature contains non-finite values");
+    }
+    Ok(embedding)
+}
+
+/// Scores a probe against a stored template of the same model. When the account
+/// has a `template_seed` the stored bytes are a protected template, and the probe
+/// is projected with the same seed so that matching happens in the transformed space.
+pub fn score_template(
+    probe: &FaceTemplate,
+    stored: &[u8],
+    seed: Option<&[u8]>,
+    config: &MatchConfig,
+) -> Result<f32> {
+    // Headerless protected templates predate the versioned format and are f32.
+    let legacy_dtype = match seed {
+        Some(_) => FeatureDtype::F32,
+        None => config.dtype,
+    };
+    let stored = FaceTemplate::parse_with(stored, legacy_dtype)?;
+    if stored.model_id != probe.model_id {
+        bail!(
+            "Template models differ: {} vs {}",
+            probe.model_id,
+            stored.model_id
+        );
+    }
+
+    match seed {
+        Some(seed) => {
+            let probe = project(probe.values(), seed, stored.dim())?;
+            config.metric.score(&probe, stored.values())
+        }
+        None => config.metric.score(probe.values(), stored.values()),
+    }
+}
+
+/// Compares a probe against a single stored template, returning whether it is
+/// accepted under `config` together with the raw score.
+pub fn verify(
+    probe: &FaceTemplate,
+    stored: &[u8],
+    seed: Option<&[u8]>,
+    config: &MatchConfig,
+) -> Result<(bool, f32)> {
+    let score = score_template(probe, stored, seed, config)?;
+    Ok((config.metric.accepts(score, config.threshold), score))
+}
+
+/// Returns the closest of the already scored candidates.
+pub fn best_match<T, I>(scored: I, metric: Metric) -> Option<(T, f32)>
+where
+    I: IntoIterator<Item = (T, f32)>,
+{
+    let mut best: Option<(T, f32)> = None;
+    for (candidate, score) in scored {
+        let better = match &best {
+            Some((_, best_score)) => metric.is_better(score, *best_score),
+            None => true,
+        };
+        if better {
+            best = Some((candidate, score));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_feature() {
+        let values = [0.5f32, -1.25, 3.0];
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(decode_feature(&bytes, FeatureDtype::F32).unwrap(), values);
+
+        let bytes: Vec<u8> = values.iter().flat_map(|v| (*v as f64).to_le_bytes()).collect();
+        assert_eq!(decode_feature(&bytes, FeatureDtype::F64).unwrap(), values);
+
+        assert!(decode_feature(&[1, 2, 3], FeatureDtype::F32).is_err());
+    }
+
+    #[test]
+    fn test_best_match() {
+        let probe = [1.0, 0.0];
+        let candidates = [
+            ("far", vec![0.0, 1.0]),
+            ("near", vec![0.9, 0.1]),
+            ("wrong_dim", vec![1.0, 0.0, 0.0]),
+        ];
+
+        for metric in [Metric::Cosine, Metric::L2] {
+            let scored = candidates.iter().filter_map(|(name, embedding)| {
+                metric.score(&probe, embedding).ok().map(|score| (*name, score))
+            });
+            let (name, score) = best_match(scored, metric).unwrap();
+            assert_eq!(name, "near");
+            assert!(metric.accepts(score, metric.default_threshold()));
+        }
+        assert!(Metric::L2.accepts(0.15, 0.2));
+        assert!(!Metric::L2.accepts(0.15, 0.1));
+    }
+
+    #[test]
+    fn test_verify_same_model_only() {
+        let config = MatchConfig {
+            metric: Metric::Cosine,
+            threshold: 0.5,
+            dtype: FeatureDtype::F32,
+        };
+        let probe = FaceTemplate::from_f32("arcface", false, &[1.0, 0.1]).unwrap();
+        let stored = FaceTemplate::from_f32("arcface", false, &[0.9, 0.0]).unwrap();
+        let other = FaceTemplate::from_f32("facenet", false, &[0.9, 0.0]).unwrap();
+
+        assert!(verify(&probe, &stored.encode(), None, &config).unwrap().0);
+        assert!(verify(&probe, &other.encode(), None, &config).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_policy() {
+        assert_eq!("Reject".parse::<DuplicatePolicy>().unwrap(), DuplicatePolicy::Reject);
+        assert_eq!("flag".parse::<DuplicatePolicy>().unwrap(), DuplicatePolicy::Flag);
+        assert_eq!("ALLOW".parse::<DuplicatePolicy>().unwrap(), DuplicatePolicy::Allow);
+        assert!("ignore".parse::<DuplicatePolicy>().is_err());
+
+        let mut config = DuplicateConfig {
+            policy: DuplicatePolicy::Reject,
+            threshold: 0.4,
+        };
+        assert!(config.is_duplicate(Metric::L2, 0.3));
+        assert!(!config.is_duplicate(Metric::L2, 0.5));
+
+        config.threshold = 0.8;
+        assert!(config.is_duplicate(Metric::Cosine, 0.9));
+        assert!(!config.is_duplicate(Metric::Cosine, 0.7));
+
+        config.policy = DuplicatePolicy::Flag;
+        assert!(config.is_duplicate(Metric::Cosine, 0.9));
+
+        config.policy = DuplicatePolicy::Allow;
+        assert!(!config.is_duplicate(Metric::Cosine, 0.9));
+        assert!(!config.is_duplicate(Metric::L2, 0.0));
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{bail, Result};
+use dotenvy::dotenv;
+use rand::{rngs::OsRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::env;
+use std::str::FromStr;
+
+pub const SEED_LEN: usize = 32;
+
+/// How templates are stored for new enrollments. With `RandomProjection` only a
+/// seeded projection of the embedding is kept, so a leaked template can be
+/// cancelled by re-issuing it under a new seed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtectionMode {
+    None,
+    RandomProjection,
+}
+
+impl FromStr for ProtectionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" | "" => Ok(ProtectionMode::None),
+            "random_projection" => Ok(ProtectionMode::RandomProjection),
+            _ => bail!("Unknown face template protection mode: {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProtectionConfig {
+    pub mode: ProtectionMode,
+    /// Output dimension of the projection; `None` halves the input dimension.
+    pub projection_dim: Option<usize>,
+}
+
+impl ProtectionConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv().ok();
+
+        let mode: ProtectionMode = env::var("FACE_TEMPLATE_PROTECTION")
+            .unwrap_or_else(|_| "none".to_string())
+            .parse()?;
+        let projection_dim = match env::var("FACE_PROJECTION_DIM") {
+            Ok(value) => Some(value.parse::<usize>()?),
+            Err(_) => None,
+        };
+
+        Ok(ProtectionConfig {
+            mode,
+            projection_dim,
+        })
+    }
+
+    /// Output dimension for a projection of `input_dim` values. It must be smaller
+    /// than the input: a square projection matrix is invertible, and anyone who
+    /// learns the seed could recover the embedding from the stored template.
+    pub fn output_dim(&self, input_dim: usize) -> Result<usize> {
+        let out_dim = self.projection_dim.unwrap_or(input_dim / 2);
+        if out_dim == 0 || out_dim >= input_dim {
+            bail!("Projection dimension must be between 1 and {}", input_dim.saturating_sub(1));
+        }
+        Ok(out_dim)
+    }
+}
+
+pub fn new_seed() -> Result<[u8; SEED_LEN]> {
+    let mut seed = [0u8; SEED_LEN];
+    OsRng.try_fill_bytes(&mut seed)?;
+    Ok(seed)
+}
+
+/// Projects `embedding` with a ±1 random matrix drawn from `seed`, scaled by
+/// 1/sqrt(out_dim) so that distances and angles are approximately preserved.
+pub fn project(embedding: &[f32], seed: &[u8], out_dim: usize) -> Result<Vec<f32>> {
+    let seed: [u8; SEED_LEN] = match seed.try_into() {
+        Ok(t) => t,
+        Err(_) => bail!("Template seed must be {} bytes", SEED_LEN),
+    };
+    if out_dim == 0 {
+        bail!("Projection dimension must be positive");
+    }
+
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    let scale = 1.0 / (out_dim as f32).sqrt();
+    let projected = (0..out_dim)
+        .map(|_| {
+            embedding
+                .iter()
+                .map(|x| if rng.gen::<bool>() { *x } else { -*x })
+                .sum::<f32>()
+                * scale
+        })
+        .collect();
+    Ok(projected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::face::Metric;
+
+    #[test]
+    fn test_project() {
+        let embedding: Vec<f32> = (0..128).map(|i| (i as f32 * 0.37).sin()).collect();
+        let nearby: Vec<f32> = embedding.iter().map(|x| x + 0.01).collect();
+        let seed = [7u8; SEED_LEN];
+
+        let protected = project(&embedding, &seed, 64).unwrap();
+        assert_eq!(protected, project(&embedding, &seed, 64).unwrap());
+        assert_ne!(protected, project(&embedding, &[8u8; SEED_LEN], 64).unwrap());
+
+        let score = Metric::Cosine
+            .score(&protected, &project(&nearby, &seed, 64).unwrap())
+            .unwrap();
+        assert!(score > 0.95);
+
+        assert!(project(&embedding, &[0u8; 4], 64).is_err());
+    }
+
+    #[test]
+    fn test_output_dim() {
+        let mut config = ProtectionConfig {
+            mode: ProtectionMode::RandomProjection,
+            projection_dim: None,
+        };
+        assert_eq!(config.output_dim(128).unwrap(), 64);
+
+        config.projection_dim = Some(96);
+        assert_eq!(config.output_dim(128).unwrap(), 96);
+
+        config.projection_dim = Some(128);
+        assert!(config.output_dim(128).is_err());
+        config.projection_dim = Some(0);
+        assert!(config.output_dim(128).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::{bail, Result};
+use dotenvy::dotenv;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use super::{decode_feature, FeatureDtype, MatchConfig};
+
+pub const MAGIC: &[u8; 4] = b"FTPL";
+pub const FORMAT_VERSION: u8 = 1;
+pub const MAX_DIM: usize = 4096;
+pub const MAX_MODEL_ID_LEN: usize = 64;
+pub const LEGACY_MODEL_ID: &str = "legacy";
+
+// magic | version | dtype | flags | model id length | dim (u16 LE)
+const HEADER_LEN: usize = 10;
+const FLAG_NORMALIZED: u8 = 0b0000_0001;
+// Quantized (f16 / i8) unit vectors are only approximately normalized.
+const NORM_TOLERANCE: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dtype {
+    F32,
+    F16,
+    I8,
+}
+
+impl Dtype {
+    fn size(&self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F16 => 2,
+            Dtype::I8 => 1,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Dtype::F32 => 0,
+            Dtype::F16 => 1,
+            Dtype::I8 => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Dtype::F32),
+            1 => Ok(Dtype::F16),
+            2 => Ok(Dtype::I8),
+            _ => bail!("Unknown template dtype: {}", byte),
+        }
+    }
+}
+
+/// A face embedding in the versioned binary format:
+///
+/// ```text
+/// "FTPL" | version u8 | dtype u8 | flags u8 | model id len u8 | dim u16 LE | model id | data
+/// ```
+///
+/// `data` holds `dim` little-endian values of `dtype`; i8 values are scaled by 1/127.
+/// Headerless payloads from older clients are still accepted as raw
+/// `FACE_FEATURE_DTYPE` arrays labelled with `FACE_LEGACY_MODEL_ID`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<u8>", into = "Vec<u8>")]
+pub struct FaceTemplate {
+    pub model_id: String,
+    pub dtype: Dtype,
+    pub normalized: bool,
+    data: Vec<u8>,
+    values: Vec<f32>,
+}
+
+impl FaceTemplate {
+    pub fn from_f32(model_id: &str, normalized: bool, values: &[f32]) -> Result<Self> {
+        let data = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        FaceTemplate::new(model_id.to_string(), Dtype::F32, normalized, data)
+    }
+
+    fn new(model_id: String, dtype: Dtype, normalized: bool, data: Vec<u8>) -> Result<Self> {
+        if model_id.is_empty() || model_id.len() > MAX_MODEL_ID_LEN {
+            bail!("Template model id must be 1 to {} bytes", MAX_MODEL_ID_LEN);
+        }
+        let values = decode_values(dtype, &data);
+        let template = FaceTemplate {
+            model_id,
+            dtype,
+            normalized,
+            data,
+            values,
+        };
+        template.validate()?;
+        Ok(template)
+    }
+
+    /// Parses either the versioned format or a legacy headerless feature of
+    /// the configured `FACE_FEATURE_DTYPE`.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        FaceTemplate::parse_with(bytes, MatchConfig::from_env()?.dtype)
+    }
+
+    pub fn parse_with(bytes: &[u8], legacy_dtype: FeatureDtype) -> Result<Self> {
+        if bytes.starts_with(MAGIC) {
+            return FaceTemplate::decode(bytes);
+        }
+
+        dotenv().ok();
+        let model_id =
+            env::var("FACE_LEGACY_MODEL_ID").unwrap_or_else(|_| LEGACY_MODEL_ID.to_string());
+        let values = decode_feature(bytes, legacy_dtype)?;
+        FaceTemplate::from_f32(&model_id, false, &values)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            bail!("Missing face template header");
+        }
+        if bytes[4] != FORMAT_VERSION {
+            bail!("Unsupported face template version: {}", bytes[4]);
+        }
+        let dtype = Dtype::from_byte(bytes[5])?;
+        let normalized = bytes[6] & FLAG_NORMALIZED != 0;
+        let model_id_len = bytes[7] as usize;
+        let dim = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+
+        let body = &bytes[HEADER_LEN..];
+        if body.len() != model_id_len + dim * dtype.size() {
+            bail!(
+                "Face template length mismatch: expected {} bytes of model id and data, got {}",
+                model_id_len + dim * dtype.size(),
+                body.len()
+            );
+        }
+        let (model_id, data) = body.split_at(model_id_len);
+        let model_id = String::from_utf8(model_id.to_vec())?;
+
+        FaceTemplate::new(model_id, dtype, normalized, data.to_vec())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.model_id.len() + self.data.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.dtype.to_byte());
+        bytes.push(if self.normalized { FLAG_NORMALIZED } else { 0 });
+        bytes.push(self.model_id.len() as u8);
+        bytes.extend_from_slice(&(self.dim() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.model_id.as_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn dim(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.values.is_empty() || self.values.len() > MAX_DIM {
+            bail!("Face template dimension must be 1 to {}", MAX_DIM);
+        }
+        if self.values.iter().any(|x| !x.is_finite()) {
+            bail!("Face template contains NaN or infinite values");
+        }
+        let norm = self.values.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            bail!("Face template has zero norm");
+        }
+        if self.normalized && (norm - 1.0).abs() > NORM_TOLERANCE {
+            bail!("Face template is flagged normalized but has norm {}", norm);
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<Vec<u8>> for FaceTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self> {
+        FaceTemplate::parse(&bytes)
+    }
+}
+
+impl From<FaceTemplate> for Vec<u8> {
+    fn from(template: FaceTemplate) -> Self {
+        template.encode()
+    }
+}
+
+fn decode_values(dtype: Dtype, data: &[u8]) -> Vec<f32> {
+    match dtype {
+        Dtype::F32 => data
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect(),
+        Dtype::F16 => data
+            .chunks_exact(2)
+            .map(|c| f16_to_f32(u16::from_le_bytes([c[0], c[1]])))
+            .collect(),
+        Dtype::I8 => data.iter().map(|b| *b as i8 as f32 / 127.0).collect(),
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let exp = ((bits >> 10) & 0x1f) as u32;
+    let frac = (bits & 0x3ff) as u32;
+    let magnitude = match exp {
+        0 => frac as f32 * 2f32.powi(-24),
+        0x1f if frac == 0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => f32::from_bits(((exp + 112) << 23) | (frac << 13)),
+    };
+    if bits & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(dtype: u8, flags: u8, model_id: &str, dim: u16) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[FORMAT_VERSION, dtype, flags, model_id.len() as u8]);
+        bytes.extend_from_slice(&dim.to_le_bytes());
+        bytes.extend_from_slice(model_id.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let template = FaceTemplate::from_f32("arcface-r100", true, &[0.6, 0.8]).unwrap();
+        let decoded = FaceTemplate::decode(&template.encode()).unwrap();
+        assert_eq!(decoded, template);
+        assert_eq!(decoded.model_id, "arcface-r100");
+        assert_eq!(decoded.values(), &[0.6, 0.8]);
+    }
+
+    #[test]
+    fn test_quantized_dtypes() {
+        // 0.5 and -1.0 as f16
+        let mut bytes = header(1, 0, "m", 2);
+        bytes.extend_from_slice(&[0x00, 0x38, 0x00, 0xbc]);
+        assert_eq!(FaceTemplate::decode(&bytes).unwrap().values(), &[0.5, -1.0]);
+
+        let mut bytes = header(2, FLAG_NORMALIZED, "m", 1);
+        bytes.push(127);
+        assert_eq!(FaceTemplate::decode(&bytes).unwrap().values(), &[1.0]);
+    }
+
+    #[test]
+    fn test_validation() {
+        // truncated data
+        let mut bytes = header(0, 0, "m", 2);
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        assert!(FaceTemplate::decode(&bytes).is_err());
+
+        // NaN
+        let mut bytes = header(0, 0, "m", 1);
+        bytes.extend_from_slice(&f32::NAN.to_le_bytes());
+        assert!(FaceTemplate::decode(&bytes).is_err());
+
+        // flagged normalized but is not
+        assert!(FaceTemplate::from_f32("m", true, &[3.0, 4.0]).is_err());
+        assert!(FaceTemplate::from_f32("m", false, &[0.0, 0.0]).is_err());
+        assert!(FaceTemplate::from_f32("", false, &[1.0]).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+use super::{bearer_token, decode_claims, SCOPE_WALLET};
+
+#[derive(Serialize, Debug)]
+struct AuthErrorResponse {
+    result: String,
+    msg: String,
+}
+
+/// Tells whether `address` is still the wallet address of the account of
+/// `uid`, so that tokens issued for a key that has since been rotated out stop
+/// working. `routes::configure` registers it as app data; without it every
+/// token is refused.
+#[derive(Clone, Copy)]
+pub struct WalletDirectory(pub fn(i64, &str) -> anyhow::Result<bool>);
+
+/// The wallet a valid `Authorization: Bearer` token was issued for.
+///
+/// Missing, expired, forged or revoked tokens, and tokens for an address the
+/// account no longer has, are rejected with 401. Handlers behind a
+/// `RequireScope` guard receive the wallet the guard already validated.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedWallet {
+    pub uid: i64,
+    pub address: String,
+    pub scopes: Vec<String>,
+    // Identifies the session the token belongs to.
+    pub jti: String,
+}
+
+impl AuthenticatedWallet {
+    pub fn from_bearer(req: &HttpRequest) -> Result<Self, Error> {
+        let token = bearer_token(req)
+            .ok_or_else(|| auth_error(StatusCode::UNAUTHORIZED, "Missing bearer token"))?;
+        let claims = decode_claims(token).map_err(|status| match status.as_u16() {
+            401 => auth_error(StatusCode::UNAUTHORIZED, "Invalid or expired token"),
+            _ => auth_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal error on `decode_claims`"),
+        })?;
+        let directory = req
+            .app_data::<WalletDirectory>()
+            .ok_or_else(|| auth_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal error on `WalletDirectory`"))?;
+        match (directory.0)(claims.uid, &claims.wallet_pubkey) {
+            Ok(true) => {}
+            Ok(false) => return Err(auth_error(StatusCode::UNAUTHORIZED, "Token was issued for a replaced wallet key")),
+            Err(_) => return Err(auth_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal error on `WalletDirectory`")),
+        }
+        Ok(AuthenticatedWallet {
+            uid: claims.uid,
+            address: claims.wallet_pubkey,
+            scopes: claims.scopes,
+            jti: claims.jti,
+        })
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    // Fails with 403 when the token was not issued for `scope`.
+    pub fn require(&self, scope: &str) -> Result<(), Error> {
+        if self.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(auth_error(StatusCode::FORBIDDEN, &format!("Token lacks the `{scope}` scope")))
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedWallet {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let guarded = req.extensions().get::<AuthenticatedWallet>().cloned();
+        ready(guarded.map_or_else(|| AuthenticatedWallet::from_bearer(req), Ok))
+    }
+}
+
+/// The wallet of a wallet-scoped bearer token, for routes that also accept
+/// other credentials. Only a request without an `Authorization` header
+/// yields `None`; a token that is sent must be valid and carry the `wallet`
+/// scope, and is otherwise refused with 401 or 403 like behind `RequireScope`.
+#[derive(Clone, Debug)]
+pub struct OptionalWallet(pub Option<AuthenticatedWallet>);
+
+impl FromRequest for OptionalWallet {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        if !req.headers().contains_key(header::AUTHORIZATION) {
+            return ready(Ok(OptionalWallet(None)));
+        }
+        let wallet = AuthenticatedWallet::from_bearer(req)
+            .and_then(|wallet| wallet.require(SCOPE_WALLET).map(|_| wallet));
+        ready(wallet.map(|wallet| OptionalWallet(Some(wallet))))
+    }
+}
+
+/// Route guard that only lets requests through whose bearer token carries
+/// `scope`:
+///
+/// ```ignore
+/// web::resource("/me")
+///     .wrap(RequireScope::new(SCOPE_WALLET))
+///     .route(web::get().to(me_get))
+/// ```
+pub struct RequireScope {
+    scope: String,
+}
+
+impl RequireScope {
+    pub fn new(scope: &str) -> Self {
+        RequireScope { scope: scope.to_string() }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireScope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequireScopeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireScopeMiddleware {
+            service,
+            scope: self.scope.clone(),
+        }))
+    }
+}
+
+pub struct RequireScopeMiddleware<S> {
+    service: S,
+    scope: String,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireScopeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let wallet = AuthenticatedWallet::from_bearer(req.request())
+            .and_then(|wallet| wallet.require(&self.scope).map(|_| wallet));
+        match wallet {
+            Ok(wallet) => {
+                req.extensions_mut().insert(wallet);
+                Box::pin(self.service.call(req))
+            }
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+}
+
+fn auth_error(status: StatusCode, msg: &str) -> Error {
+    let mut response = HttpResponse::build(status);
+    if status == StatusCode::UNAUTHORIZED {
+        response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+    }
+    let body = AuthErrorResponse {
+        result: "Error".to_string(),
+        msg: msg.to_string(),
+    };
+    InternalError::from_response(msg.to_string(), response.json(body)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jwt::{issue_token, SCOPE_WALLET};
+    use actix_web::{test, web, App, HttpResponse, Responder};
+
+    async fn me(wallet: AuthenticatedWallet) -> impl Responder {
+        HttpResponse::Ok().body(wallet.address)
+    }
+
+    // Wallet "abc" of uid 1 has been rotated to "def".
+    fn current_wallet(user_id: i64, address: &str) -> anyhow::Result<bool> {
+        Ok(user_id == 1 && address == "def")
+    }
+
+    fn me_request(token: Option<&str>) -> test::TestRequest {
+        let req = test::TestRequest::get().uri("/me");
+        match token {
+            Some(t) => req.insert_header((header::AUTHORIZATION, format!("Bearer {t}"))),
+            None => req,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_require_scope() {
+        let app = test::init_service(
+            App::new().app_data(WalletDirectory(current_wallet)).service(
+                web::resource("/me")
+                    .wrap(RequireScope::new(SCOPE_WALLET))
+                    .route(web::get().to(me)),
+            ),
+        )
+        .await;
+
+        let missing = test::try_call_service(&app, me_request(None).to_request()).await;
+        assert_eq!(missing.unwrap_err().error_response().status(), StatusCode::UNAUTHORIZED);
+
+        let forged = test::try_call_service(&app, me_request(Some("not.a.token")).to_request()).await;
+        assert_eq!(forged.unwrap_err().error_response().status(), StatusCode::UNAUTHORIZED);
+
+        let token = issue_token("def".to_string(), 1, &["sign"]).unwrap().token;
+        let wrong_scope = test::try_call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(wrong_scope.unwrap_err().error_response().status(), StatusCode::FORBIDDEN);
+
+        let token = issue_token("def".to_string(), 1, &[SCOPE_WALLET]).unwrap().token;
+        let allowed = test::call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(allowed.status(), StatusCode::OK);
+        assert_eq!(test::read_body(allowed).await, "def");
+    }
+
+    async fn maybe_me(wallet: OptionalWallet) -> impl Responder {
+        HttpResponse::Ok().body(wallet.0.map_or_else(|| "none".to_string(), |wallet| wallet.address))
+    }
+
+    #[actix_web::test]
+    async fn test_optional_wallet() {
+        let app = test::init_service(
+            App::new()
+                .app_data(WalletDirectory(current_wallet))
+                .route("/me", web::get().to(maybe_me)),
+        )
+        .await;
+
+        let missing = test::call_service(&app, me_request(None).to_request()).await;
+        assert_eq!(missing.status(), StatusCode::OK);
+        assert_eq!(test::read_body(missing).await, "none");
+
+        // A token that is sent is never treated as missing.
+        let forged = test::call_service(&app, me_request(Some("not.a.token")).to_request()).await;
+        assert_eq!(forged.status(), StatusCode::UNAUTHORIZED);
+
+        let token = issue_token("def".to_string(), 1, &["sign"]).unwrap().token;
+        let wrong_scope = test::call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(wrong_scope.status(), StatusCode::FORBIDDEN);
+
+        let token = issue_token("def".to_string(), 1, &[SCOPE_WALLET]).unwrap();
+        crate::jwt::revocation::revoke_token(&token.jti, token.exp);
+        let revoked = test::call_service(&app, me_request(Some(&token.token)).to_request()).await;
+        assert_eq!(revoked.status(), StatusCode::UNAUTHORIZED);
+
+        let token = issue_token("def".to_string(), 1, &[SCOPE_WALLET]).unwrap().token;
+        let allowed = test::call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(allowed.status(), StatusCode::OK);
+        assert_eq!(test::read_body(allowed).await, "def");
+    }
+
+    #[actix_web::test]
+    async fn test_rotated_wallet() {
+        let app = test::init_service(
+            App::new()
+                .app_data(WalletDirectory(current_wallet))
+                .route("/me", web::get().to(me)),
+        )
+        .await;
+
+        // Issued before the key was rotated.
+        let token = issue_token("abc".to_string(), 1, &[SCOPE_WALLET]).unwrap().token;
+        let stale = test::call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(stale.status(), StatusCode::UNAUTHORIZED);
+
+        let token = issue_token("def".to_string(), 2, &[SCOPE_WALLET]).unwrap().token;
+        let other_user = test::call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(other_user.status(), StatusCode::UNAUTHORIZED);
+
+        let token = issue_token("def".to_string(), 1, &[SCOPE_WALLET]).unwrap().token;
+        let current = test::call_service(&app, me_request(Some(&token)).to_request()).await;
+        assert_eq!(current.status(), StatusCode::OK);
+    }
+}
@@ -1,27 +1,48 @@
+use actix_web::{http::header, HttpRequest};
 use dotenvy::dotenv;
 use hyper::StatusCode;
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::env;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod middleware;
+pub mod revocation;
+
+use revocation::is_revoked;
+
+// Access to the wallet the token was issued for.
+pub const SCOPE_WALLET: &str = "wallet";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Claims {
     wallet_pubkey: String,
     uid: i64,
     exp: usize,
     nbf: usize,
+    // Tokens issued before scopes were introduced carry none.
+    #[serde(default)]
+    scopes: Vec<String>,
+    // Identifies the token in the session list and the revocation cache.
+    // Tokens issued before sessions were recorded carry none and belong to no
+    // session; they still expire and stop working once the key is rotated.
+    #[serde(default)]
+    jti: String,
 }
 
-pub fn generate_token(wallet_pubkey: String, uid: i64) -> Result<String, StatusCode> {
-    dotenv().ok();
+// An access token together with the claims its session is recorded under.
+pub struct IssuedToken {
+    pub token: String,
+    pub jti: String,
+    pub exp: usize,
+}
 
-    let start = SystemTime::now();
-    let since_the_epoch = start
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
+pub fn issue_token(wallet_pubkey: String, uid: i64, scopes: &[&str]) -> Result<IssuedToken, StatusCode> {
+    dotenv().ok();
 
-    let in_sec = since_the_epoch.as_secs() as usize;
+    let in_sec = now_secs();
 
     let exp_time_str = env::var("JWT_EXPIRATION_TIME").expect("JWT_EXPIRATION_TIME must be set");
     let exp_time = exp_time_str
@@ -38,24 +59,73 @@ pub fn generate_token(wallet_pubkey: String, uid: i64) -> Result<String, StatusC
         uid,
         exp: in_sec + exp_time, // + 1 hour
         nbf: in_sec - not_before,
+        scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        jti: hex::encode(rand::random::<[u8; 16]>()),
     };
 
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     let key = EncodingKey::from_secret(secret.as_bytes());
-    encode(&Header::default(), &claims, &key).map_err(|_error| StatusCode::INTERNAL_SERVER_ERROR)
+    let token = encode(&Header::default(), &claims, &key).map_err(|_error| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(IssuedToken {
+        token,
+        jti: claims.jti,
+        exp: claims.exp,
+    })
 }
 
 pub fn is_valid(token: &str) -> Result<(bool, i64, String), StatusCode> {
+    let claims = decode_claims(token)?;
+    Ok((true, claims.uid, claims.wallet_pubkey))
+}
+
+// Expired, malformed, forged or revoked tokens are the caller's fault and map to 401.
+fn decode_claims(token: &str) -> Result<Claims, StatusCode> {
+    dotenv().ok();
+
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     let key = DecodingKey::from_secret(secret.as_bytes());
     let token_data =
         decode::<Claims>(token, &key, &Validation::new(Algorithm::HS256)).map_err(|error| {
             match error.kind() {
-                jsonwebtoken::errors::ErrorKind::ExpiredSignature => StatusCode::UNAUTHORIZED,
+                ErrorKind::ExpiredSignature
+                | ErrorKind::ImmatureSignature
+                | ErrorKind::InvalidSignature
+                | ErrorKind::InvalidToken
+                | ErrorKind::InvalidAlgorithm
+                | ErrorKind::Base64(_)
+                | ErrorKind::Json(_)
+                | ErrorKind::Utf8(_) => StatusCode::UNAUTHORIZED,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             }
         })?;
-    Ok((true, token_data.claims.uid, token_data.claims.wallet_pubkey))
+    if is_revoked(&token_data.claims.jti) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(token_data.claims)
+}
+
+fn now_secs() -> usize {
+    let start = SystemTime::now();
+    let since_the_epoch = start
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+
+    since_the_epoch.as_secs() as usize
+}
+
+// Refresh tokens are random, so an unsalted hash is enough to keep the stored
+// copies useless to whoever reads the table.
+pub fn hash_refresh_token(refresh_token: &str) -> String {
+    hex::encode(Sha256::digest(refresh_token.as_bytes()))
+}
+
+// Extracts the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
 }
 
 #[cfg(test)]
@@ -64,11 +134,65 @@ mod test {
 
     #[test]
     fn test_is_valid() {
-        let token = generate_token("abc".to_string(), 1).unwrap();
+        let token = issue_token("abc".to_string(), 1, &[SCOPE_WALLET]).unwrap().token;
 
         let (is_valid, uid, wallet_pubkey) = is_valid(&token).unwrap();
         assert_eq!(is_valid, true);
         assert_eq!(uid, 1);
         assert_eq!(wallet_pubkey, "abc".to_string());
     }
+
+    #[test]
+    fn test_scoped_token() {
+        let issued = issue_token("abc".to_string(), 1, &["sign"]).unwrap();
+
+        let claims = decode_claims(&issued.token).unwrap();
+        assert_eq!(claims.scopes, vec!["sign".to_string()]);
+        assert_eq!(claims.jti, issued.jti);
+        assert_eq!(decode_claims("not.a.token").unwrap_err(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_revoked_token() {
+        let issued = issue_token("abc".to_string(), 1, &[SCOPE_WALLET]).unwrap();
+        let other = issue_token("abc".to_string(), 1, &[SCOPE_WALLET]).unwrap();
+        assert_ne!(issued.jti, other.jti);
+
+        revocation::revoke_token(&issued.jti, issued.exp);
+        assert_eq!(is_valid(&issued.token).unwrap_err(), StatusCode::UNAUTHORIZED);
+        assert!(is_valid(&other.token).is_ok());
+    }
+
+    #[test]
+    fn test_token_without_jti() {
+        #[derive(Serialize)]
+        struct LegacyClaims {
+            wallet_pubkey: String,
+            uid: i64,
+            exp: usize,
+            nbf: usize,
+        }
+
+        let legacy = LegacyClaims {
+            wallet_pubkey: "abc".to_string(),
+            uid: 1,
+            exp: now_secs() + 60,
+            nbf: now_secs() - 60,
+        };
+        dotenv().ok();
+        let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+        let token = encode(&Header::default(), &legacy, &EncodingKey::from_secret(secret.as_bytes())).unwrap();
+
+        let claims = decode_claims(&token).unwrap();
+        assert_eq!(claims.jti, "");
+        assert!(claims.scopes.is_empty());
+    }
+
+    #[test]
+    fn test_hash_refresh_token() {
+        let hashed = hash_refresh_token("refresh");
+        assert_eq!(hashed.len(), 64);
+        assert_eq!(hashed, hash_refresh_token("refresh"));
+        assert_ne!(hashed, hash_refresh_token("refresh2"));
+    }
 }
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+use super::now_secs;
+
+// `jti` -> `exp` of every revoked token that has not expired yet. Filled as
+// sessions are revoked and synced from the `session` table, so checking a
+// token never hits the database.
+static REVOKED: OnceLock<RwLock<HashMap<String, usize>>> = OnceLock::new();
+
+fn revoked() -> &'static RwLock<HashMap<String, usize>> {
+    REVOKED.get_or_init(Default::default)
+}
+
+pub fn revoke_token(jti: &str, exp: usize) {
+    revoke_tokens([(jti.to_string(), exp)]);
+}
+
+// Adds revoked tokens to the cache and drops those that have expired since;
+// an expired token is rejected by its `exp` alone.
+pub fn revoke_tokens(tokens: impl IntoIterator<Item = (String, usize)>) {
+    let now = now_secs();
+    let mut cache = revoked().write().unwrap_or_else(PoisonError::into_inner);
+    cache.retain(|_, exp| *exp > now);
+    cache.extend(tokens.into_iter().filter(|(_, exp)| *exp > now));
+}
+
+pub fn is_revoked(jti: &str) -> bool {
+    revoked()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains_key(jti)
+}
@@ -0,0 +1,201 @@
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use rand::{rngs::OsRng, RngCore};
+use scrypt::{scrypt, Params};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sp_keyring::sr25519::sr25519::Pair;
+use xsalsa20poly1305::aead::{Aead, KeyInit};
+use xsalsa20poly1305::{Nonce, XSalsa20Poly1305};
+use zeroize::Zeroizing;
+
+use cess_rust_sdk::subxt::ext::sp_core::Pair as sp_core_pair;
+
+use crate::secret::SecretString;
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+// PKCS8 framing used by polkadot-js around the 64-byte secret and 32-byte public key.
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+const SECRET_KEY_LEN: usize = 64;
+const PUBLIC_KEY_LEN: usize = 32;
+
+// polkadot-js only accepts its default scrypt parameters (N = 2^15, p = 1, r = 8).
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_P: u32 = 1;
+const SCRYPT_R: u32 = 8;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const KEYSTORE_VERSION: &str = "3";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct KeystoreEncoding {
+    pub content: Vec<String>,
+    #[serde(rename = "type")]
+    pub kind: Vec<String>,
+    pub version: String,
+}
+
+/// A polkadot-js / Talisman JSON keystore (version 3) holding an sr25519 key.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Keystore {
+    pub encoded: String,
+    pub encoding: KeystoreEncoding,
+    pub address: String,
+    #[serde(default)]
+    pub meta: serde_json::Value,
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let params = Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, 32)
+        .map_err(|_| anyhow!("Invalid scrypt parameters"))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt(password.as_bytes(), salt, &params, key.as_mut_slice())
+        .map_err(|_| anyhow!("scrypt failed"))?;
+    Ok(key)
+}
+
+/// Encrypts `pair` into a keystore for `address` under `password`.
+pub fn encrypt_keystore(pair: &Pair, address: &str, password: &str, name: &str) -> Result<Keystore> {
+    if password.len() < MIN_PASSWORD_LEN {
+        bail!("Keystore password must be at least {} characters", MIN_PASSWORD_LEN);
+    }
+
+    let mut plaintext = Zeroizing::new(PKCS8_HEADER.to_vec());
+    plaintext.extend_from_slice(Zeroizing::new(pair.as_ref().secret.to_ed25519_bytes()).as_slice());
+    plaintext.extend_from_slice(&PKCS8_DIVIDER);
+    plaintext.extend_from_slice(&pair.public().0);
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.try_fill_bytes(&mut salt)?;
+    OsRng.try_fill_bytes(&mut nonce)?;
+
+    let cipher = XSalsa20Poly1305::new_from_slice(derive_key(password, &salt)?.as_slice())
+        .map_err(|_| anyhow!("Invalid keystore key"))?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| anyhow!("Keystore encryption failed"))?;
+
+    // salt || N || p || r || nonce || secretbox, integers little-endian
+    let mut encoded = salt.to_vec();
+    encoded.extend_from_slice(&(1u32 << SCRYPT_LOG_N).to_le_bytes());
+    encoded.extend_from_slice(&SCRYPT_P.to_le_bytes());
+    encoded.extend_from_slice(&SCRYPT_R.to_le_bytes());
+    encoded.extend_from_slice(&nonce);
+    encoded.extend_from_slice(&ciphertext);
+
+    Ok(Keystore {
+        encoded: STANDARD.encode(encoded),
+        encoding: KeystoreEncoding {
+            content: vec!["pkcs8".to_string(), "sr25519".to_string()],
+            kind: vec!["scrypt".to_string(), "xsalsa20-poly1305".to_string()],
+            version: KEYSTORE_VERSION.to_string(),
+        },
+        address: address.to_string(),
+        meta: json!({ "name": name, "whenCreated": Utc::now().timestamp_millis() }),
+    })
+}
+
+// polkadot-js stores the secret in its ed25519 expanded form, whose scalar is
+// multiplied by the cofactor 8; sp-core takes schnorrkel's own encoding.
+fn from_ed25519_bytes(bytes: &[u8]) -> Zeroizing<[u8; SECRET_KEY_LEN]> {
+    let mut secret = Zeroizing::new([0u8; SECRET_KEY_LEN]);
+    secret.copy_from_slice(bytes);
+
+    let mut carry = 0u8;
+    for byte in secret[..32].iter_mut().rev() {
+        let remainder = *byte & 0b111;
+        *byte = (*byte >> 3) | carry;
+        carry = remainder << 5;
+    }
+    secret
+}
+
+/// Decrypts a keystore, checking that the embedded public key matches the secret.
+pub fn decrypt_keystore(keystore: &Keystore, password: &str) -> Result<Pair> {
+    let encoding = &keystore.encoding;
+    if encoding.version != KEYSTORE_VERSION
+        || !encoding.kind.iter().any(|k| k == "scrypt")
+        || !encoding.kind.iter().any(|k| k == "xsalsa20-poly1305")
+    {
+        bail!("Unsupported keystore encoding");
+    }
+    if !encoding.content.iter().any(|c| c == "sr25519") {
+        bail!("Only sr25519 keystores are supported");
+    }
+
+    let encoded = STANDARD.decode(&keystore.encoded)?;
+    let params_end = SALT_LEN + 12;
+    if encoded.len() < params_end + NONCE_LEN {
+        bail!("Keystore is truncated");
+    }
+    let (salt, rest) = encoded.split_at(SALT_LEN);
+    let param = |i: usize| u32::from_le_bytes([rest[i], rest[i + 1], rest[i + 2], rest[i + 3]]);
+    if (param(0), param(4), param(8)) != (1u32 << SCRYPT_LOG_N, SCRYPT_P, SCRYPT_R) {
+        bail!("Unsupported scrypt parameters");
+    }
+    let (nonce, ciphertext) = encoded[params_end..].split_at(NONCE_LEN);
+
+    let cipher = XSalsa20Poly1305::new_from_slice(derive_key(password, salt)?.as_slice())
+        .map_err(|_| anyhow!("Invalid keystore key"))?;
+    let plaintext = Zeroizing::new(cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Wrong keystore password"))?);
+
+    let secret_end = PKCS8_HEADER.len() + SECRET_KEY_LEN;
+    let public_start = secret_end + PKCS8_DIVIDER.len();
+    if plaintext.len() != public_start + PUBLIC_KEY_LEN
+        || plaintext[..PKCS8_HEADER.len()] != PKCS8_HEADER
+        || plaintext[secret_end..public_start] != PKCS8_DIVIDER
+    {
+        bail!("Invalid PKCS8 keystore content");
+    }
+
+    let secret = from_ed25519_bytes(&plaintext[PKCS8_HEADER.len()..secret_end]);
+    let pair = match Pair::from_seed_slice(secret.as_slice()) {
+        Ok(pair) => pair,
+        Err(_) => bail!("Invalid sr25519 secret key"),
+    };
+    if pair.public().0[..] != plaintext[public_start..] {
+        bail!("Keystore public key does not match its secret");
+    }
+    Ok(pair)
+}
+
+/// The `0x` hex secret key stored for wallets imported without a mnemonic;
+/// `accounts::get_pair` accepts it in place of a phrase.
+pub fn secret_seed(pair: &Pair) -> SecretString {
+    let secret = Zeroizing::new(pair.as_ref().secret.to_bytes());
+    let secret_hex = Zeroizing::new(hex::encode(secret.as_slice()));
+    SecretString::from(format!("0x{}", secret_hex.as_str()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controllers::accounts::get_pair;
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let pair = get_pair(
+            "bottom drive obey lake curtain smoke basket hold race lonely fit walk",
+            None,
+        )
+        .unwrap();
+        let keystore = encrypt_keystore(&pair, "cXabc", "correct horse", "savings").unwrap();
+        assert_eq!(keystore.meta["name"], "savings");
+
+        let json = serde_json::to_string(&keystore).unwrap();
+        let parsed: Keystore = serde_json::from_str(&json).unwrap();
+        let imported = decrypt_keystore(&parsed, "correct horse").unwrap();
+        assert_eq!(imported.public(), pair.public());
+        assert!(decrypt_keystore(&parsed, "wrong horse").is_err());
+        assert!(encrypt_keystore(&pair, "cXabc", "short", "").is_err());
+
+        let restored = get_pair(secret_seed(&imported).expose_secret(), None).unwrap();
+        assert_eq!(restored.public(), pair.public());
+    }
+}
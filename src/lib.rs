@@ -1,6 +1,14 @@
+pub mod backup;
 pub mod controllers;
+pub mod crypto;
 pub mod databases;
+pub mod face;
+pub mod rotation;
 pub mod routes;
 pub mod utils;
 pub mod jwt;
-pub mod schema;
\ No newline at end of file
+pub mod keystore;
+pub mod schema;
+pub mod secret;
+pub mod siwe;
+pub mod vault;
\ No newline at end of file
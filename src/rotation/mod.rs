@@ -0,0 +1,88 @@
+/// What sweeping one address replaced by a key rotation did, or would do for
+/// the given balances.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SweepOutcome {
+    /// The transferable balance was moved to the new address.
+    pub transferred: bool,
+    /// Reserved or frozen funds are left on the old address until they are
+    /// released, and only the old key can move them then.
+    pub held: bool,
+}
+
+impl SweepOutcome {
+    // `frozen` is the amount `free + reserved` may not drop below, so the part
+    // of it covered by reserved funds does not lock any free balance.
+    pub fn of_balance(free: u128, reserved: u128, frozen: u128) -> Self {
+        SweepOutcome {
+            transferred: free > frozen.saturating_sub(reserved),
+            held: reserved > 0 || frozen > 0,
+        }
+    }
+}
+
+/// Where a key rotation stands once each of its old addresses has been swept.
+#[derive(Debug, PartialEq)]
+pub enum SweepProgress {
+    /// A sweep failed; the old key is kept and the rotation can be retried.
+    Failed,
+    /// Funds are still held on an old address; the old key is kept until a
+    /// retry finds them released.
+    Held,
+    /// Every old address is empty, so the old key can be deleted.
+    Done { transferred: bool },
+}
+
+pub fn sweep_progress<E>(outcomes: &[Result<SweepOutcome, E>]) -> SweepProgress {
+    if outcomes.iter().any(Result::is_err) {
+        return SweepProgress::Failed;
+    }
+    let outcomes: Vec<&SweepOutcome> = outcomes.iter().flatten().collect();
+    if outcomes.iter().any(|outcome| outcome.held) {
+        return SweepProgress::Held;
+    }
+    SweepProgress::Done { transferred: outcomes.iter().any(|outcome| outcome.transferred) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_of_balance() {
+        assert_eq!(SweepOutcome::of_balance(0, 0, 0), SweepOutcome { transferred: false, held: false });
+        assert_eq!(SweepOutcome::of_balance(10, 0, 0), SweepOutcome { transferred: true, held: false });
+        // Frozen free balance cannot move.
+        assert_eq!(SweepOutcome::of_balance(10, 0, 10), SweepOutcome { transferred: false, held: true });
+        assert_eq!(SweepOutcome::of_balance(10, 0, 4), SweepOutcome { transferred: true, held: true });
+        // A freeze covered by reserved funds leaves the free balance movable.
+        assert_eq!(SweepOutcome::of_balance(10, 5, 5), SweepOutcome { transferred: true, held: true });
+        assert_eq!(SweepOutcome::of_balance(0, 5, 0), SweepOutcome { transferred: false, held: true });
+    }
+
+    #[test]
+    fn test_sweep_progress() {
+        let moved = SweepOutcome { transferred: true, held: false };
+        let held = SweepOutcome { transferred: true, held: true };
+
+        assert_eq!(sweep_progress::<()>(&[]), SweepProgress::Done { transferred: false });
+        assert_eq!(sweep_progress::<()>(&[Ok(moved), Ok(SweepOutcome::default())]), SweepProgress::Done { transferred: true });
+        assert_eq!(sweep_progress::<()>(&[Ok(moved), Ok(held)]), SweepProgress::Held);
+        // One failed sub-account sweep keeps the old key, even if the others moved.
+        assert_eq!(sweep_progress(&[Ok(moved), Err("sub-account"), Ok(held)]), SweepProgress::Failed);
+    }
+
+    #[test]
+    fn test_sweep_retry() {
+        // The first attempt moves the main balance but fails on a sub-account.
+        let first = [Ok(SweepOutcome::of_balance(10, 0, 0)), Err("timeout")];
+        assert_eq!(sweep_progress(&first), SweepProgress::Failed);
+
+        // A retry finds the main address already empty and finishes the rest.
+        let retry: [Result<SweepOutcome, &str>; 2] = [Ok(SweepOutcome::of_balance(0, 0, 0)), Ok(SweepOutcome::of_balance(3, 0, 0))];
+        assert_eq!(sweep_progress(&retry), SweepProgress::Done { transferred: true });
+
+        // Retrying a finished sweep transfers nothing and is still done.
+        let again: [Result<SweepOutcome, &str>; 2] = [Ok(SweepOutcome::default()), Ok(SweepOutcome::default())];
+        assert_eq!(sweep_progress(&again), SweepProgress::Done { transferred: false });
+    }
+}
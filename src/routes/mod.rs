@@ -1,7 +1,31 @@
 use crate::controllers::controllers::*;
+use crate::databases::{establish_connection, is_current_wallet};
+use crate::jwt::{middleware::{RequireScope, WalletDirectory}, SCOPE_WALLET};
 use actix_web::web;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
+    // Lets bearer tokens be checked against the account's current address.
+    cfg.app_data(WalletDirectory(|user_id, wallet_address| {
+        is_current_wallet(&mut establish_connection(), user_id, wallet_address)
+    }));
+    // Bearer-only routes; registered ahead of the catch-all scope below.
+    cfg.service(
+        web::resource("/me")
+            .wrap(RequireScope::new(SCOPE_WALLET))
+            .route(web::get().to(me_get))
+    );
+    cfg.service(
+        web::resource("/logout")
+            .wrap(RequireScope::new(SCOPE_WALLET))
+            .route(web::post().to(logout_post))
+    );
+    cfg.service(
+        web::scope("/sessions")
+            .wrap(RequireScope::new(SCOPE_WALLET))
+            .route("", web::get().to(sessions_get))
+            .route("/revoke", web::post().to(revoke_session_post))
+            .route("/revoke_all", web::post().to(revoke_all_sessions_post))
+    );
     cfg.service(
         web::scope("")
             .route("/", web::get().to(index)) // GET request to "/"
@@ -9,5 +33,24 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/get_wallet", web::post().to(get_wallet_post))
             .route("/create_wallet", web::post().to(create_wallet_post)) 
             .route("/recover_wallet", web::post().to(recover_wallet_post)) 
+            .route("/identify", web::post().to(identify_post))
+            .route("/reissue_template", web::post().to(reissue_template_post))
+            .route("/mnemonic/reveal_ticket", web::post().to(reveal_ticket_post))
+            .route("/mnemonic/reveal", web::post().to(reveal_mnemonic_post))
+            .route("/auth/challenge", web::post().to(auth_challenge_post))
+            .route("/auth/verify", web::post().to(auth_verify_post))
+            .route("/siwe/nonce", web::get().to(siwe_nonce_get))
+            .route("/siwe/verify", web::post().to(siwe_verify_post))
+            .route("/sub_accounts/create", web::post().to(create_sub_account_post))
+            .route("/sub_accounts/rename", web::post().to(rename_sub_account_post))
+            .route("/sub_accounts/list", web::post().to(list_sub_accounts_post))
+            .route("/keystore/export", web::post().to(export_keystore_post))
+            .route("/keystore/import", web::post().to(import_keystore_post))
+            .route("/import_wallet/challenge", web::get().to(import_challenge_get))
+            .route("/import_wallet", web::post().to(import_wallet_post))
+            .route("/sign", web::post().to(sign_post))
+            .route("/recover_from_shares", web::post().to(recover_from_shares_post))
+            .route("/rotate_key", web::post().to(rotate_key_post))
+            .route("/token/refresh", web::post().to(refresh_token_post))
     );
 }
\ No newline at end of file
@@ -11,5 +11,195 @@ diesel::table! {
         #[max_length = 256]
         token -> Nullable<Varchar>,
         feature -> Nullable<Bytea>,
+        last_match_score -> Nullable<Float4>,
+        mnemonic_ciphertext -> Nullable<Bytea>,
+        mnemonic_data_key -> Nullable<Bytea>,
+        mnemonic_key_version -> Nullable<Int4>,
+        feature_key_version -> Nullable<Int4>,
+        template_seed -> Nullable<Bytea>,
+        duplicate_of -> Nullable<Int8>,
+        #[max_length = 64]
+        feature_model -> Nullable<Varchar>,
+        #[max_length = 42]
+        evm_address -> Nullable<Varchar>,
+        #[max_length = 16]
+        key_scheme -> Varchar,
+        ss58_prefix -> Int4,
+        template_seed_key_version -> Nullable<Int4>,
+        #[max_length = 16]
+        secret_kind -> Varchar,
+        public_key -> Nullable<Bytea>,
     }
 }
+
+diesel::table! {
+    auth_challenge (id) {
+        id -> Int8,
+        account_id -> Int8,
+        #[max_length = 64]
+        nonce -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    import_challenge (id) {
+        id -> Int8,
+        #[max_length = 64]
+        nonce -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        #[max_length = 256]
+        address -> Varchar,
+    }
+}
+
+diesel::table! {
+    key_rotation (id) {
+        id -> Int8,
+        account_id -> Int8,
+        #[max_length = 256]
+        old_address -> Varchar,
+        #[max_length = 42]
+        old_evm_address -> Nullable<Varchar>,
+        #[max_length = 256]
+        new_address -> Varchar,
+        old_mnemonic_ciphertext -> Nullable<Bytea>,
+        old_mnemonic_data_key -> Nullable<Bytea>,
+        old_mnemonic_key_version -> Nullable<Int4>,
+        swept_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    mnemonic_reveal_audit (id) {
+        id -> Int8,
+        account_id -> Int8,
+        ticket_id -> Nullable<Int8>,
+        #[max_length = 32]
+        outcome -> Varchar,
+        #[max_length = 64]
+        ip -> Nullable<Varchar>,
+        #[max_length = 256]
+        user_agent -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    refresh_token (id) {
+        id -> Int8,
+        account_id -> Int8,
+        #[max_length = 64]
+        family_id -> Varchar,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    reveal_ticket (id) {
+        id -> Int8,
+        account_id -> Int8,
+        #[max_length = 64]
+        ticket -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    session (id) {
+        id -> Int8,
+        account_id -> Int8,
+        #[max_length = 64]
+        jti -> Varchar,
+        #[max_length = 64]
+        family_id -> Varchar,
+        #[max_length = 256]
+        device -> Nullable<Varchar>,
+        #[max_length = 64]
+        ip -> Nullable<Varchar>,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    signature_audit (id) {
+        id -> Int8,
+        account_id -> Int8,
+        sub_account_id -> Nullable<Int8>,
+        #[max_length = 64]
+        scope -> Varchar,
+        #[max_length = 64]
+        payload_hash -> Varchar,
+        payload_len -> Int4,
+        face_verified -> Bool,
+        #[max_length = 64]
+        ip -> Nullable<Varchar>,
+        #[max_length = 256]
+        user_agent -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    siwe_nonce (id) {
+        id -> Int8,
+        #[max_length = 64]
+        nonce -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sub_account (id) {
+        id -> Int8,
+        account_id -> Int8,
+        #[max_length = 64]
+        name -> Varchar,
+        #[max_length = 256]
+        derivation_path -> Varchar,
+        #[max_length = 256]
+        address -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(auth_challenge -> account (account_id));
+diesel::joinable!(key_rotation -> account (account_id));
+diesel::joinable!(mnemonic_reveal_audit -> account (account_id));
+diesel::joinable!(mnemonic_reveal_audit -> reveal_ticket (ticket_id));
+diesel::joinable!(refresh_token -> account (account_id));
+diesel::joinable!(reveal_ticket -> account (account_id));
+diesel::joinable!(session -> account (account_id));
+diesel::joinable!(signature_audit -> account (account_id));
+diesel::joinable!(signature_audit -> sub_account (sub_account_id));
+diesel::joinable!(sub_account -> account (account_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    account,
+    auth_challenge,
+    import_challenge,
+    key_rotation,
+    mnemonic_reveal_audit,
+    refresh_token,
+    reveal_ticket,
+    session,
+    signature_audit,
+    siwe_nonce,
+    sub_account,
+);
@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::{Pg, PgValue};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Deserializer, Serializer};
+use zeroize::{Zeroize, Zeroizing};
+
+/// A mnemonic phrase, `0x` hex secret key or password.
+///
+/// The buffer is wiped when the value is dropped, and the type implements
+/// neither `Debug`, `Display` nor `Serialize`, so it cannot be logged or sent
+/// in a response by accident:
+///
+/// ```compile_fail
+/// use cess_rust_server::secret::SecretString;
+///
+/// let phrase = SecretString::from("bottom drive obey lake curtain smoke basket hold race lonely fit walk".to_string());
+/// println!("{:?}", phrase);
+/// ```
+///
+/// ```compile_fail
+/// use cess_rust_server::secret::SecretString;
+///
+/// let phrase = SecretString::from("bottom drive obey lake curtain smoke basket hold race lonely fit walk".to_string());
+/// serde_json::to_string(&phrase).unwrap();
+/// ```
+///
+/// Reading the secret is always spelled out:
+///
+/// ```
+/// use cess_rust_server::secret::SecretString;
+///
+/// let phrase = SecretString::from("bottom drive obey lake curtain smoke basket hold race lonely fit walk".to_string());
+/// assert!(phrase.expose_secret().starts_with("bottom"));
+/// ```
+#[derive(Clone, Default)]
+pub struct SecretString(Zeroizing<String>);
+
+impl SecretString {
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        SecretString(Zeroizing::new(secret))
+    }
+}
+
+// Takes over a decrypted buffer without copying it. Invalid UTF-8 is wiped
+// before the error is returned.
+impl TryFrom<SecretBytes> for SecretString {
+    type Error = anyhow::Error;
+
+    fn try_from(mut secret: SecretBytes) -> Result<Self> {
+        match String::from_utf8(std::mem::take(&mut *secret.0)) {
+            Ok(t) => Ok(SecretString::from(t)),
+            Err(e) => {
+                e.into_bytes().zeroize();
+                bail!("Secret is not valid UTF-8")
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString::from)
+    }
+}
+
+// Lets rows holding a plaintext mnemonic load straight into a `SecretString`.
+impl FromSql<Text, Pg> for SecretString {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        <String as FromSql<Text, Pg>>::from_sql(bytes).map(SecretString::from)
+    }
+}
+
+/// Raw secret key material, such as a decrypted envelope or a BIP39 seed.
+/// Wiped on drop and, like `SecretString`, neither `Debug` nor `Serialize`:
+///
+/// ```compile_fail
+/// use cess_rust_server::secret::SecretBytes;
+///
+/// let seed = SecretBytes::from(vec![7u8; 32]);
+/// println!("{:?}", seed);
+/// ```
+#[derive(Clone)]
+pub struct SecretBytes(Zeroizing<Vec<u8>>);
+
+impl SecretBytes {
+    pub fn expose_secret(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(secret: Vec<u8>) -> Self {
+        SecretBytes(Zeroizing::new(secret))
+    }
+}
+
+// Serializes a secret on purpose, for the few responses whose job is to hand
+// it out: `#[serde(serialize_with = "expose_secret")]`.
+pub fn expose_secret<S: Serializer>(secret: &SecretString, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(secret.expose_secret())
+}
+
+// Like `expose_secret`, for a list of secrets.
+pub fn expose_secrets<S: Serializer>(secrets: &[SecretString], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(secrets.iter().map(SecretString::expose_secret))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_secret_from_utf8() {
+        let phrase = SecretString::try_from(SecretBytes::from(b"abandon ability".to_vec())).unwrap();
+        assert_eq!(phrase.expose_secret(), "abandon ability");
+        assert!(SecretString::try_from(SecretBytes::from(vec![0xff, 0xfe])).is_err());
+
+        let parsed: SecretString = serde_json::from_str("\"abandon ability\"").unwrap();
+        assert_eq!(parsed.expose_secret(), "abandon ability");
+    }
+
+    #[test]
+    fn test_expose_secrets() {
+        #[derive(serde::Serialize)]
+        struct Shares {
+            #[serde(serialize_with = "expose_secrets")]
+            shares: Vec<SecretString>,
+        }
+
+        let shares = Shares { shares: vec![SecretString::from("01ab".to_string()), SecretString::from("02cd".to_string())] };
+        assert_eq!(serde_json::to_string(&shares).unwrap(), r#"{"shares":["01ab","02cd"]}"#);
+    }
+}
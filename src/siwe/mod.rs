@@ -0,0 +1,254 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use dotenvy::dotenv;
+use std::env;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Split;
+
+use crate::controllers::accounts::to_checksum_address;
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+const MIN_NONCE_LEN: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub enum SiweError {
+    Malformed(&'static str),
+    UnsupportedVersion(String),
+    DomainMismatch(String),
+    ChainIdMismatch(u64),
+    Expired,
+    NotYetValid,
+}
+
+impl fmt::Display for SiweError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SiweError::Malformed(reason) => write!(f, "Malformed SIWE message: {}", reason),
+            SiweError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported SIWE version: {}", version)
+            }
+            SiweError::DomainMismatch(domain) => write!(f, "Unexpected SIWE domain: {}", domain),
+            SiweError::ChainIdMismatch(chain_id) => write!(f, "Unsupported chain id: {}", chain_id),
+            SiweError::Expired => write!(f, "SIWE message has expired"),
+            SiweError::NotYetValid => write!(f, "SIWE message is not valid yet"),
+        }
+    }
+}
+
+impl std::error::Error for SiweError {}
+
+/// Domain and chains this server accepts sign-in messages for.
+#[derive(Clone, Debug)]
+pub struct SiweConfig {
+    pub domain: String,
+    pub chain_ids: Vec<u64>,
+}
+
+impl SiweConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        dotenv().ok();
+
+        let domain = env::var("SIWE_DOMAIN").map_err(|_| anyhow!("SIWE_DOMAIN must be set"))?;
+        let chain_ids = env::var("SIWE_CHAIN_IDS")
+            .unwrap_or_else(|_| "1".to_string())
+            .split(',')
+            .map(|id| id.trim().parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SiweConfig { domain, chain_ids })
+    }
+}
+
+/// An EIP-4361 "Sign-In with Ethereum" message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SiweMessage {
+    pub domain: String,
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+// Consumes the next line if it starts with `tag`, returning the rest of it.
+fn tagged<'a>(lines: &mut Peekable<Split<'a, char>>, tag: &str) -> Option<&'a str> {
+    let value = lines.peek()?.strip_prefix(tag)?;
+    lines.next();
+    Some(value)
+}
+
+fn timestamp(value: &str) -> Result<DateTime<Utc>, SiweError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| SiweError::Malformed("invalid RFC 3339 timestamp"))
+}
+
+impl SiweMessage {
+    pub fn parse(message: &str) -> Result<Self, SiweError> {
+        let mut lines = message.split('\n').peekable();
+
+        let header = lines.next().unwrap_or_default();
+        let domain = header
+            .strip_suffix(HEADER_SUFFIX)
+            .ok_or(SiweError::Malformed("invalid header"))?;
+        let domain = domain.split_once("://").map_or(domain, |(_, d)| d);
+
+        let address = lines.next().unwrap_or_default();
+        if to_checksum_address(address).as_deref() != Some(address) {
+            return Err(SiweError::Malformed("address is not EIP-55 checksummed"));
+        }
+        if lines.next() != Some("") {
+            return Err(SiweError::Malformed("expected a blank line after the address"));
+        }
+
+        let statement = match lines.peek() {
+            Some(line) if line.starts_with("URI: ") => None,
+            Some(&"") => {
+                lines.next();
+                None
+            }
+            Some(line) => {
+                let statement = line.to_string();
+                lines.next();
+                if lines.next() != Some("") {
+                    return Err(SiweError::Malformed("expected a blank line after the statement"));
+                }
+                Some(statement)
+            }
+            None => return Err(SiweError::Malformed("message is truncated")),
+        };
+
+        let uri = tagged(&mut lines, "URI: ").ok_or(SiweError::Malformed("missing URI"))?;
+        let version = tagged(&mut lines, "Version: ").ok_or(SiweError::Malformed("missing Version"))?;
+        let chain_id = tagged(&mut lines, "Chain ID: ")
+            .and_then(|id| id.parse::<u64>().ok())
+            .ok_or(SiweError::Malformed("missing or invalid Chain ID"))?;
+        let nonce = tagged(&mut lines, "Nonce: ").ok_or(SiweError::Malformed("missing Nonce"))?;
+        if nonce.len() < MIN_NONCE_LEN || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SiweError::Malformed("nonce must be at least 8 alphanumeric characters"));
+        }
+        let issued_at = timestamp(
+            tagged(&mut lines, "Issued At: ").ok_or(SiweError::Malformed("missing Issued At"))?,
+        )?;
+        let expiration_time = tagged(&mut lines, "Expiration Time: ").map(timestamp).transpose()?;
+        let not_before = tagged(&mut lines, "Not Before: ").map(timestamp).transpose()?;
+        let request_id = tagged(&mut lines, "Request ID: ").map(str::to_string);
+
+        let mut resources = Vec::new();
+        if tagged(&mut lines, "Resources:") == Some("") {
+            while let Some(resource) = tagged(&mut lines, "- ") {
+                resources.push(resource.to_string());
+            }
+        }
+        if lines.any(|line| !line.is_empty()) {
+            return Err(SiweError::Malformed("unexpected trailing content"));
+        }
+
+        Ok(SiweMessage {
+            domain: domain.to_string(),
+            address: address.to_string(),
+            statement,
+            uri: uri.to_string(),
+            version: version.to_string(),
+            chain_id,
+            nonce: nonce.to_string(),
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+
+    /// Checks the message was meant for this server and is currently valid. The
+    /// signature and the nonce are checked separately by the caller.
+    pub fn validate(&self, config: &SiweConfig, now: DateTime<Utc>) -> Result<(), SiweError> {
+        if self.version != "1" {
+            return Err(SiweError::UnsupportedVersion(self.version.clone()));
+        }
+        if self.domain != config.domain {
+            return Err(SiweError::DomainMismatch(self.domain.clone()));
+        }
+        if !config.chain_ids.contains(&self.chain_id) {
+            return Err(SiweError::ChainIdMismatch(self.chain_id));
+        }
+        if self.expiration_time.is_some_and(|t| now >= t) {
+            return Err(SiweError::Expired);
+        }
+        if self.not_before.is_some_and(|t| now < t) {
+            return Err(SiweError::NotYetValid);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ADDRESS: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    fn message(statement: Option<&str>, extra: &str) -> String {
+        let statement = statement.map(|s| format!("{}\n", s)).unwrap_or_default();
+        format!(
+            "wallet.example.com wants you to sign in with your Ethereum account:\n{}\n\n{}\nURI: https://wallet.example.com/login\nVersion: 1\nChain ID: 1\nNonce: 32891756aB\nIssued At: 2026-10-18T12:00:00Z{}",
+            ADDRESS, statement, extra
+        )
+    }
+
+    fn config() -> SiweConfig {
+        SiweConfig {
+            domain: "wallet.example.com".to_string(),
+            chain_ids: vec![1],
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = SiweMessage::parse(&message(
+            Some("Sign in to the face wallet."),
+            "\nExpiration Time: 2026-10-18T12:10:00Z\nResources:\n- ipfs://bafybei",
+        ))
+        .unwrap();
+        assert_eq!(parsed.domain, "wallet.example.com");
+        assert_eq!(parsed.address, ADDRESS);
+        assert_eq!(parsed.statement.as_deref(), Some("Sign in to the face wallet."));
+        assert_eq!(parsed.chain_id, 1);
+        assert_eq!(parsed.nonce, "32891756aB");
+        assert!(parsed.expiration_time.is_some());
+        assert_eq!(parsed.resources, vec!["ipfs://bafybei".to_string()]);
+
+        let parsed = SiweMessage::parse(&message(None, "")).unwrap();
+        assert_eq!(parsed.statement, None);
+
+        let lowercase = message(None, "").replace(ADDRESS, &ADDRESS.to_lowercase());
+        assert!(SiweMessage::parse(&lowercase).is_err());
+        assert!(SiweMessage::parse(&message(None, "").replace("Nonce: 32891756aB", "Nonce: 1")).is_err());
+        assert!(SiweMessage::parse(&message(None, "\nUnknown: field")).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let issued = DateTime::parse_from_rfc3339("2026-10-18T12:00:00Z").unwrap().with_timezone(&Utc);
+        let later = DateTime::parse_from_rfc3339("2026-10-18T12:20:00Z").unwrap().with_timezone(&Utc);
+
+        let parsed = SiweMessage::parse(&message(None, "\nExpiration Time: 2026-10-18T12:10:00Z")).unwrap();
+        assert_eq!(parsed.validate(&config(), issued), Ok(()));
+        assert_eq!(parsed.validate(&config(), later), Err(SiweError::Expired));
+
+        let mut other = config();
+        other.domain = "evil.example.com".to_string();
+        assert!(matches!(parsed.validate(&other, issued), Err(SiweError::DomainMismatch(_))));
+
+        other = config();
+        other.chain_ids = vec![137];
+        assert_eq!(parsed.validate(&other, issued), Err(SiweError::ChainIdMismatch(1)));
+    }
+}
@@ -32,6 +32,11 @@ use cess_rust_sdk::chain::deoss::DeOss;
 use cess_rust_sdk::polkadot::runtime_types::pallet_file_bank::types::FileState;
 use cess_rust_sdk::subxt::ext::sp_core::Pair as sp_core_pair;
 
+use crate::controllers::accounts::{derive_pair, get_wallet_pair, KeyScheme, WalletPair};
+use crate::rotation::SweepOutcome;
+use crate::secret::SecretString;
+use crate::vault::{KeySlot, KeyVault};
+
 const LOG_TARGET: &str = "Utils";
 
 pub fn generate_code(code_len: usize) -> String {
@@ -152,16 +157,19 @@ pub fn file_exists(file_path: &str) -> bool {
     path.exists() && path.is_file()
 }
 
-pub fn get_decloud_wallet() -> Result<String> {
+pub fn get_decloud_wallet() -> Result<SecretString> {
     dotenv().ok();
     let authorizer_mnemonic =
         env::var("DECLOUD_TREASURY_ACCOUNT").expect("DECLOUD_TREASURY_ACCOUNT must be set");
 
-    Ok(authorizer_mnemonic)
+    Ok(SecretString::from(authorizer_mnemonic))
 }
 
-pub async fn authorize_account_to_upload_to_gateway(mnenomic: &str) -> Result<()> {
-    let sdk = init_chain(mnenomic);
+// Authorizes the DeOSS gateway to upload for the wallet in `slot`. The chain
+// SDK only takes a mnemonic, so the key is revealed for the call.
+pub async fn authorize_account_to_upload_to_gateway(vault: &dyn KeyVault, slot: KeySlot) -> Result<()> {
+    let mnenomic = vault.reveal(slot)?;
+    let sdk = init_chain(mnenomic.expose_secret());
 
     let deoss_account = get_deoss_account();
 
@@ -287,7 +295,7 @@ pub async fn download_file_from_gateway(
 pub async fn send_rewards(address: &str, amount: BigDecimal) -> Result<()> {
     let decloud_wallet = get_decloud_wallet()?;
     let pair =
-        <sp_keyring::sr25519::sr25519::Pair as sp_core_pair>::from_string(&decloud_wallet, None)
+        <sp_keyring::sr25519::sr25519::Pair as sp_core_pair>::from_string(decloud_wallet.expose_secret(), None)
             .unwrap();
     let from = PairSigner::new(pair.clone());
     let pk_bytes = parsing_public_key(address).unwrap();
@@ -308,6 +316,80 @@ pub async fn send_rewards(address: &str, amount: BigDecimal) -> Result<()> {
     Ok(())
 }
 
+// Moves the transferable balance of the key in `slot`, or of the key derived
+// from it at `derivation_path`, to `address`. Reserved and frozen funds cannot
+// be moved yet and are reported as held.
+pub async fn sweep_balance(
+    vault: &dyn KeyVault,
+    slot: KeySlot,
+    scheme: KeyScheme,
+    derivation_path: Option<&str>,
+    address: &str,
+) -> Result<SweepOutcome> {
+    let pair = get_wallet_pair(vault.reveal(slot)?.expose_secret(), None, scheme)?;
+    let pair = match derivation_path {
+        Some(path) => derive_pair(&pair, path)?,
+        None => pair,
+    };
+
+    let source = account_from_slice(&<[u8; 32]>::from(pair.account_id()));
+    let query = polkadot::storage().system().account(&source);
+    let outcome = match query_storage(&query, None).await? {
+        Some(info) => SweepOutcome::of_balance(info.data.free, info.data.reserved, info.data.frozen),
+        None => SweepOutcome::default(),
+    };
+    if !outcome.transferred {
+        return Ok(outcome);
+    }
+
+    let (dest, _) = AccountId32::from_ss58check_with_version(address)
+        .map_err(|e| anyhow::anyhow!("Invalid address {}: {:?}", address, e))?;
+    let dest_bytes: &[u8; 32] = dest.as_ref();
+    let dest = account_from_slice(dest_bytes);
+
+    let transfer_all_tx = polkadot::tx().balances().transfer_all(
+        cess_rust_sdk::subxt::utils::MultiAddress::Id(dest),
+        false,
+    );
+
+    let events = match &pair {
+        WalletPair::Sr25519(pair) => {
+            sign_and_submit_tx_then_watch_default(&transfer_all_tx, &PairSigner::new(pair.clone())).await?
+        }
+        WalletPair::Ed25519(pair) => {
+            sign_and_submit_tx_then_watch_default(&transfer_all_tx, &PairSigner::new(**pair)).await?
+        }
+        WalletPair::Ecdsa(pair) => {
+            sign_and_submit_tx_then_watch_default(&transfer_all_tx, &PairSigner::new(pair.clone())).await?
+        }
+    };
+
+    let transfer_event = events.find_first::<polkadot::balances::events::Transfer>()?;
+    if let Some(event) = transfer_event {
+        info!(target: LOG_TARGET, "Balance sweep success: {event:?}");
+    }
+
+    Ok(outcome)
+}
+
+// Names of the buckets an account owns.
+pub async fn user_buckets(address: &str) -> Result<Vec<String>> {
+    let pk_bytes = parsing_public_key(address)?;
+    let account = account_from_slice(&pk_bytes);
+    let query = polkadot::storage().file_bank().user_bucket_list(&account);
+    let result = query_storage(&query, None).await?;
+    let names = match result {
+        Some(list) => list
+            .0
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(&name.0).to_string())
+            .collect(),
+        None => vec![],
+    };
+
+    Ok(names)
+}
+
 pub async fn create_bucket(bucket_name: &str, signed_msg: &str, account: &str) -> Result<bool> {
     let url = get_deoss_url();
     let client = Client::new();
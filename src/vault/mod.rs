@@ -0,0 +1,343 @@
+use anyhow::{anyhow, bail, Result};
+use cess_rust_sdk::subxt::ext::sp_core::crypto::AccountId32;
+use diesel::pg::PgConnection;
+use dotenvy::dotenv;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::env;
+
+use crate::controllers::accounts::{derive_pair, get_evm_address, get_wallet_pair, KeyScheme, WalletPair};
+use crate::crypto::{open, seal, Envelope, Keyring};
+use crate::databases::{
+    accounts_without_evm_address, clear_account_mnemonic, clear_rotated_mnemonic, decrypt_mnemonic,
+    decrypt_rotated_mnemonic, establish_connection, find_account, find_key_rotation, set_evm_address,
+    store_account_mnemonic, store_rotated_mnemonic, MNEMONIC_KEY_PREFIX,
+};
+use crate::keystore::{encrypt_keystore, Keystore};
+use crate::secret::SecretString;
+
+// Version byte of the key files written by `FileVault`.
+const FILE_VAULT_VERSION: u8 = 1;
+
+/// The secret a vault entry holds: the current key of an account, or the
+/// replaced key of a key rotation whose balance has not been swept yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeySlot {
+    Account(i64),
+    Rotation(i64),
+}
+
+/// Custody of wallet secrets. Callers address a key by its slot and get back
+/// signatures, account ids and addresses instead of the mnemonic.
+///
+/// Every backend implements each operation itself, so one whose keys cannot
+/// leave it, such as an HSM, never has its keys pulled out through `reveal`
+/// behind its back. It may refuse `reveal`, which is only used for the
+/// user-facing mnemonic reveal and for chain calls that need a signer.
+pub trait KeyVault: Send + Sync {
+    fn store(&self, slot: KeySlot, secret: &SecretString) -> Result<()>;
+
+    fn reveal(&self, slot: KeySlot) -> Result<SecretString>;
+
+    // Removing a slot that holds nothing is not an error.
+    fn delete(&self, slot: KeySlot) -> Result<()>;
+
+    fn copy(&self, from: KeySlot, to: KeySlot) -> Result<()>;
+
+    // Signs `msg` with the key of the slot, or with the key derived from it at
+    // `derivation_path`.
+    fn sign(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>, msg: &[u8]) -> Result<Vec<u8>>;
+
+    fn derive(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>) -> Result<AccountId32>;
+
+    // Address of the linked EVM account. Only a mnemonic carries the BIP39 seed
+    // it is derived from, so secrets imported as raw keys have none.
+    fn evm_address(&self, slot: KeySlot) -> Result<Option<String>>;
+
+    fn export_keystore(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>, address: &str, password: &str, name: &str) -> Result<Keystore>;
+}
+
+// The operations of the backends below, which keep the secret itself and open
+// it in process for each of them.
+fn secret_pair(secret: &SecretString, scheme: KeyScheme, derivation_path: Option<&str>) -> Result<WalletPair> {
+    let pair = get_wallet_pair(secret.expose_secret(), None, scheme)?;
+    match derivation_path {
+        Some(path) => derive_pair(&pair, path),
+        None => Ok(pair)
+    }
+}
+
+fn secret_keystore(secret: &SecretString, scheme: KeyScheme, derivation_path: Option<&str>, address: &str, password: &str, name: &str) -> Result<Keystore> {
+    match &secret_pair(secret, scheme, derivation_path)? {
+        WalletPair::Sr25519(pair) => encrypt_keystore(pair, address, password, name),
+        _ => bail!("Only sr25519 wallets can be exported as a keystore")
+    }
+}
+
+// Backend selected by `KEY_VAULT`: `postgres` (the default) or `file`, which
+// keeps its files in `KEY_VAULT_DIR`.
+pub fn vault_from_env() -> Result<Box<dyn KeyVault>> {
+    dotenv().ok();
+
+    match env::var("KEY_VAULT").unwrap_or_else(|_| "postgres".to_string()).as_str() {
+        "postgres" => Ok(Box::new(PgVault)),
+        "file" => {
+            let dir = env::var("KEY_VAULT_DIR").map_err(|_| anyhow!("KEY_VAULT_DIR must be set"))?;
+            Ok(Box::new(FileVault::new(dir, Keyring::from_env(MNEMONIC_KEY_PREFIX)?)?))
+        },
+        other => bail!("Unsupported key vault `{}`", other)
+    }
+}
+
+// Derives and stores the EVM address of every account created before EVM
+// addresses were linked, returning the number of rows updated.
+pub fn derive_evm_addresses(conn: &mut PgConnection, vault: &dyn KeyVault) -> Result<usize> {
+    let mut updated = 0;
+    for row in accounts_without_evm_address(conn)? {
+        // Wallets imported from a raw key have no EVM account.
+        let linked_evm_address = match vault.evm_address(KeySlot::Account(row.id)) {
+            Ok(Some(t)) => t,
+            _ => continue
+        };
+        set_evm_address(conn, row.id, &linked_evm_address)?;
+        updated += 1;
+    }
+    Ok(updated)
+}
+
+/// Keeps secrets in the database, sealed under a per-row data key wrapped by
+/// the `MNEMONIC` master keys: account keys on their `account` row and
+/// replaced keys on their `key_rotation` row.
+pub struct PgVault;
+
+impl KeyVault for PgVault {
+    fn store(&self, slot: KeySlot, secret: &SecretString) -> Result<()> {
+        let connection = &mut establish_connection();
+        match slot {
+            KeySlot::Account(account_id) => store_account_mnemonic(connection, account_id, secret),
+            KeySlot::Rotation(rotation_id) => store_rotated_mnemonic(connection, rotation_id, secret)
+        }
+    }
+
+    fn reveal(&self, slot: KeySlot) -> Result<SecretString> {
+        let connection = &mut establish_connection();
+        match slot {
+            KeySlot::Account(account_id) => {
+                let account_data = find_account(connection, account_id)
+                    .ok_or_else(|| anyhow!("Account {} does not exist", account_id))?;
+                decrypt_mnemonic(&account_data)?.ok_or_else(|| anyhow!("Account {} has no key", account_id))
+            },
+            KeySlot::Rotation(rotation_id) => {
+                let rotation = find_key_rotation(connection, rotation_id)
+                    .filter(|t| t.old_mnemonic_ciphertext.is_some())
+                    .ok_or_else(|| anyhow!("Key rotation {} holds no key", rotation_id))?;
+                decrypt_rotated_mnemonic(&rotation)
+            }
+        }
+    }
+
+    fn delete(&self, slot: KeySlot) -> Result<()> {
+        let connection = &mut establish_connection();
+        match slot {
+            KeySlot::Account(account_id) => clear_account_mnemonic(connection, account_id),
+            KeySlot::Rotation(rotation_id) => clear_rotated_mnemonic(connection, rotation_id)
+        }
+    }
+
+    fn copy(&self, from: KeySlot, to: KeySlot) -> Result<()> {
+        self.store(to, &self.reveal(from)?)
+    }
+
+    fn sign(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>, msg: &[u8]) -> Result<Vec<u8>> {
+        Ok(secret_pair(&self.reveal(slot)?, scheme, derivation_path)?.sign(msg))
+    }
+
+    fn derive(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>) -> Result<AccountId32> {
+        Ok(secret_pair(&self.reveal(slot)?, scheme, derivation_path)?.account_id())
+    }
+
+    fn evm_address(&self, slot: KeySlot) -> Result<Option<String>> {
+        Ok(get_evm_address(self.reveal(slot)?.expose_secret()).ok())
+    }
+
+    fn export_keystore(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>, address: &str, password: &str, name: &str) -> Result<Keystore> {
+        secret_keystore(&self.reveal(slot)?, scheme, derivation_path, address, password, name)
+    }
+}
+
+/// Keeps each secret in its own file under `dir`, sealed like `PgVault` rows,
+/// so a database dump holds no key material.
+///
+/// File layout: `version u8 | key_version i32 BE | wrapped key length u16 BE |
+/// wrapped key | ciphertext`.
+pub struct FileVault {
+    dir: PathBuf,
+    keyring: Keyring,
+}
+
+impl FileVault {
+    pub fn new(dir: impl Into<PathBuf>, keyring: Keyring) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        Ok(FileVault { dir, keyring })
+    }
+
+    // Binds a key file to its slot, so a file renamed to another slot does not open.
+    fn aad(slot: KeySlot) -> Vec<u8> {
+        match slot {
+            KeySlot::Account(account_id) => format!("account/{}", account_id).into_bytes(),
+            KeySlot::Rotation(rotation_id) => format!("key_rotation/{}", rotation_id).into_bytes()
+        }
+    }
+
+    fn path(&self, slot: KeySlot) -> PathBuf {
+        match slot {
+            KeySlot::Account(account_id) => self.dir.join(format!("account-{}.key", account_id)),
+            KeySlot::Rotation(rotation_id) => self.dir.join(format!("rotation-{}.key", rotation_id))
+        }
+    }
+}
+
+impl KeyVault for FileVault {
+    fn store(&self, slot: KeySlot, secret: &SecretString) -> Result<()> {
+        let envelope = seal(&self.keyring, secret.expose_secret().as_bytes(), &FileVault::aad(slot))?;
+        let wrapped_len = u16::try_from(envelope.wrapped_key.len())?;
+
+        let mut encoded = vec![FILE_VAULT_VERSION];
+        encoded.extend_from_slice(&envelope.key_version.to_be_bytes());
+        encoded.extend_from_slice(&wrapped_len.to_be_bytes());
+        encoded.extend_from_slice(&envelope.wrapped_key);
+        encoded.extend_from_slice(&envelope.ciphertext);
+
+        // Written next to the target and renamed over it, so a crash never
+        // leaves a truncated key behind.
+        let path = self.path(slot);
+        let staged = path.with_extension("key.tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&staged)?;
+        file.write_all(&encoded)?;
+        file.sync_all()?;
+        fs::rename(&staged, &path)?;
+        Ok(())
+    }
+
+    fn reveal(&self, slot: KeySlot) -> Result<SecretString> {
+        let encoded = match fs::read(self.path(slot)) {
+            Ok(t) => t,
+            Err(e) if e.kind() == ErrorKind::NotFound => bail!("No key stored for {:?}", slot),
+            Err(e) => return Err(e.into())
+        };
+        if encoded.len() < 7 || encoded[0] != FILE_VAULT_VERSION {
+            bail!("Unsupported key file for {:?}", slot);
+        }
+        let key_version = i32::from_be_bytes(encoded[1..5].try_into()?);
+        let wrapped_len = u16::from_be_bytes(encoded[5..7].try_into()?) as usize;
+        if encoded.len() < 7 + wrapped_len {
+            bail!("Truncated key file for {:?}", slot);
+        }
+
+        let envelope = Envelope {
+            ciphertext: encoded[7 + wrapped_len..].to_vec(),
+            wrapped_key: encoded[7..7 + wrapped_len].to_vec(),
+            key_version
+        };
+        SecretString::try_from(open(&self.keyring, &envelope, &FileVault::aad(slot))?)
+    }
+
+    fn delete(&self, slot: KeySlot) -> Result<()> {
+        match fs::remove_file(self.path(slot)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    fn copy(&self, from: KeySlot, to: KeySlot) -> Result<()> {
+        self.store(to, &self.reveal(from)?)
+    }
+
+    fn sign(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>, msg: &[u8]) -> Result<Vec<u8>> {
+        Ok(secret_pair(&self.reveal(slot)?, scheme, derivation_path)?.sign(msg))
+    }
+
+    fn derive(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>) -> Result<AccountId32> {
+        Ok(secret_pair(&self.reveal(slot)?, scheme, derivation_path)?.account_id())
+    }
+
+    fn evm_address(&self, slot: KeySlot) -> Result<Option<String>> {
+        Ok(get_evm_address(self.reveal(slot)?.expose_secret()).ok())
+    }
+
+    fn export_keystore(&self, slot: KeySlot, scheme: KeyScheme, derivation_path: Option<&str>, address: &str, password: &str, name: &str) -> Result<Keystore> {
+        secret_keystore(&self.reveal(slot)?, scheme, derivation_path, address, password, name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controllers::accounts::{format_ss58, generate_mnemonic, verify_signed_msg, WalletFormat};
+    use bip39::Language;
+
+    const KEY: &str = "1:000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn file_vault(name: &str) -> FileVault {
+        let dir = env::temp_dir().join(format!("face-wallet-vault-{}-{}", name, std::process::id()));
+        FileVault::new(dir, Keyring::parse(KEY, 1).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_file_vault_round_trip() {
+        let vault = file_vault("round-trip");
+        let phrase = generate_mnemonic(12, Language::English).unwrap();
+
+        vault.store(KeySlot::Account(1), &phrase).unwrap();
+        assert_eq!(vault.reveal(KeySlot::Account(1)).unwrap().expose_secret(), phrase.expose_secret());
+        assert!(vault.reveal(KeySlot::Rotation(1)).is_err());
+        vault.copy(KeySlot::Account(1), KeySlot::Rotation(1)).unwrap();
+        assert_eq!(vault.reveal(KeySlot::Rotation(1)).unwrap().expose_secret(), phrase.expose_secret());
+
+        // Files hold no plaintext.
+        let encoded = fs::read(vault.path(KeySlot::Account(1))).unwrap();
+        assert!(!String::from_utf8_lossy(&encoded).contains(phrase.expose_secret()));
+        // and are bound to their slot.
+        fs::write(vault.path(KeySlot::Account(3)), &encoded).unwrap();
+        assert!(vault.reveal(KeySlot::Account(3)).is_err());
+
+        vault.delete(KeySlot::Account(1)).unwrap();
+        assert!(vault.reveal(KeySlot::Account(1)).is_err());
+        vault.delete(KeySlot::Account(1)).unwrap();
+        fs::remove_dir_all(&vault.dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_vault_signs_and_derives() {
+        let vault = file_vault("sign");
+        let phrase = generate_mnemonic(12, Language::English).unwrap();
+        vault.store(KeySlot::Account(2), &phrase).unwrap();
+
+        for scheme in [KeyScheme::Sr25519, KeyScheme::Ed25519, KeyScheme::Ecdsa] {
+            let expected = get_wallet_pair(phrase.expose_secret(), None, scheme).unwrap().account_id();
+            let account_id = vault.derive(KeySlot::Account(2), scheme, None).unwrap();
+            assert_eq!(account_id, expected);
+
+            let signature = vault.sign(KeySlot::Account(2), scheme, None, b"payload").unwrap();
+            let signer = format_ss58(&account_id, 42);
+            let format = WalletFormat::new(scheme, 42).unwrap();
+            assert!(verify_signed_msg(&hex::encode(signature), b"payload", &signer, format).unwrap());
+
+            let pair = get_wallet_pair(phrase.expose_secret(), None, scheme).unwrap();
+            let expected = derive_pair(&pair, "//0").unwrap().account_id();
+            assert_eq!(vault.derive(KeySlot::Account(2), scheme, Some("//0")).unwrap(), expected);
+        }
+        assert_eq!(vault.evm_address(KeySlot::Account(2)).unwrap(), get_evm_address(phrase.expose_secret()).ok());
+        fs::remove_dir_all(&vault.dir).unwrap();
+    }
+}